@@ -1,8 +1,22 @@
 mod render;
 
-use tide::{Body, Request, Response};
-use femme;
-use rustracer_core::scene::Scene;
+use std::time::{Duration, Instant};
+
+use render::{RenderError, RenderOptions};
+use tide::{Body, Request, Response, StatusCode};
+use rustracer_core::aov::Aov;
+use rustracer_core::heatmap::CostMetric;
+use rustracer_core::output::{ImageFormat, OutputError};
+use rustracer_core::progressive::{Budget, CancellationToken};
+use rustracer_core::migration;
+use rustracer_core::scene::{Scene, SceneError};
+use rustracer_core::stats::Timings;
+
+// renders still going after this long are cancelled and answered with 503
+const MAX_RENDER_TIME: Duration = Duration::from_secs(60);
+// progressive renders stop adding passes after this long, leaving time to
+// post-process and encode before MAX_RENDER_TIME
+const MAX_PROGRESSIVE_TIME: Duration = Duration::from_secs(50);
 
 #[async_std::main]
 async fn main() -> tide::Result<()> {
@@ -19,11 +33,106 @@ async fn main() -> tide::Result<()> {
 }
 
 async fn render(mut req: Request<()>) -> tide::Result {
-    let scene: Scene = req.body_json().await?;
+    let options = render_options(&req)?;
+    let body = req.body_string().await?;
+    // the upload isn't part of loading
+    let load_start = Instant::now();
+    let mut document: serde_json::Value = serde_json::from_str(&body)
+        .map_err(|e| bad_request(SceneError::Parse(e.to_string())))?;
+    migration::scale_legacy_api_colors(&mut document);
+    let scene = Scene::from_document(document).map_err(bad_request)?;
+    let timings = Timings { scene_load: load_start.elapsed(), ..Timings::default() };
     println!("Rendering scene: {:?}", scene);
-    let image = render::handle_render(scene);
+    let format = options.format;
+    let token = CancellationToken::new();
+    let deadline = async_std::task::spawn({
+        let token = token.clone();
+        async move {
+            async_std::task::sleep(MAX_RENDER_TIME).await;
+            token.cancel();
+        }
+    });
+    // off the async executor, so other requests are served meanwhile
+    let result = async_std::task::spawn_blocking(move || render::handle_render(scene, &options, timings, &token)).await;
+    deadline.cancel().await;
+    let (image, stats) = result.map_err(|e| match e {
+        RenderError::Cancelled => tide::Error::from_str(StatusCode::ServiceUnavailable, format!("the render took longer than {}s", MAX_RENDER_TIME.as_secs())),
+        e => tide::Error::from_str(StatusCode::InternalServerError, e.to_string()),
+    })?;
+    println!("{}", stats);
     let mut response = Response::new(200);
     response.set_body(Body::from_bytes(image));
-    response.set_content_type("image/jpeg");
+    response.set_content_type(format.mime_type());
+    response.insert_header("Server-Timing", server_timing(&stats.timings));
+    response.insert_header("X-Render-Stats", serde_json::to_string(&stats)?);
     Ok(response)
-}
\ No newline at end of file
+}
+
+// the phases in milliseconds, which browser dev tools show next to the request
+fn server_timing(timings: &Timings) -> String {
+    timings.phases().iter()
+        .map(|(name, duration)| format!("{};dur={:.3}", name, duration.as_secs_f64() * 1000.0))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn bad_request(message: impl std::fmt::Display) -> tide::Error {
+    tide::Error::from_str(StatusCode::BadRequest, message.to_string())
+}
+
+/// Reads the render options from the query string:
+///
+/// - `format`: image format, jpeg by default
+/// - `aov`: comma separated output passes. EXR adds them as layers next to
+///   the shaded image. Any other format holds a single pass, which is
+///   returned in place of the shaded image, as it was traced: without
+///   exposure, denoising, bloom or alpha, so those options are refused
+///   alongside it.
+/// - `denoise`, `alpha`: flags, a bare name turns them on
+/// - `heatmap`: return a false colour picture of a cost metric instead
+/// - `passes`, `time_limit`: render progressively within this budget, and
+///   never for longer than `MAX_PROGRESSIVE_TIME`
+///
+/// Any render is cancelled after `MAX_RENDER_TIME`.
+fn render_options(req: &Request<()>) -> tide::Result<RenderOptions> {
+    let query = |name: &str| req.url().query_pairs().find(|(key, _)| key == name).map(|(_, value)| value.into_owned());
+    let format = match query("format") {
+        Some(value) => value.parse::<ImageFormat>().map_err(bad_request)?,
+        None => ImageFormat::Jpeg,
+    };
+    let aovs = match query("aov") {
+        Some(value) => Aov::parse_list(&value).map_err(bad_request)?,
+        None => Vec::new(),
+    };
+    if aovs.len() > 1 && !matches!(format, ImageFormat::Exr(_)) {
+        return Err(bad_request("only exr can hold more than one aov"));
+    }
+    // a bare ?denoise turns it on too
+    let denoise = query("denoise").is_some_and(|value| value != "false" && value != "0");
+    let alpha = query("alpha").is_some_and(|value| value != "false" && value != "0");
+    if alpha && !format.supports_alpha() {
+        return Err(bad_request(OutputError::NoAlpha(format)));
+    }
+    let heatmap = match query("heatmap") {
+        Some(value) => Some(value.parse::<CostMetric>().map_err(bad_request)?),
+        None => None,
+    };
+    let pass_only = !aovs.is_empty() && !matches!(format, ImageFormat::Exr(_));
+    if pass_only && (denoise || alpha || heatmap.is_some() || query("passes").is_some() || query("time_limit").is_some()) {
+        return Err(bad_request(format!("a {} aov is returned as traced, it can't be combined with denoise, alpha, heatmap, passes or time_limit", format)));
+    }
+
+    let mut budget: Option<Budget> = None;
+    if let Some(value) = query("passes") {
+        let passes = value.parse::<u32>().ok().filter(|n| *n > 0).ok_or(bad_request(format!("invalid number of passes: {}", value)))?;
+        budget.get_or_insert_with(Budget::default).passes = Some(passes);
+    }
+    if let Some(value) = query("time_limit") {
+        let seconds = value.parse::<f32>().ok().filter(|s| *s > 0.0 && s.is_finite()).ok_or(bad_request(format!("invalid time limit: {}", value)))?;
+        budget.get_or_insert_with(Budget::default).time = Some(Duration::from_secs_f32(seconds));
+    }
+    if let Some(budget) = &mut budget {
+        budget.time = Some(budget.time.map_or(MAX_PROGRESSIVE_TIME, |time| time.min(MAX_PROGRESSIVE_TIME)));
+    }
+    Ok(RenderOptions { format, aovs, denoise, alpha, heatmap, budget })
+}
@@ -1,32 +1,133 @@
-use rustracer_core::{graphics::vec_writer::VecWriter, raytracer, scene::Scene};
+use rustracer_core::aov::{self, Aov};
+use rustracer_core::denoise::Denoiser;
+use rustracer_core::graphics::tonemap::DisplayTransform;
+use rustracer_core::heatmap::{self, CostMetric};
+use rustracer_core::output::{self, ImageFormat, OutputError};
+use rustracer_core::progressive::{self, Budget, CancellationToken};
+use rustracer_core::region::Region;
+use rustracer_core::stats::{timed, RenderStats, Timings};
+use rustracer_core::{raytracer, scene::Scene};
+use std::fmt;
 use std::sync::Arc;
 
-pub fn handle_render(scene: Scene) -> Vec<u8> {
-    let raytracer = Arc::new(raytracer::Raytracer::new(normalize_colors(scene)));
-    let px_width = raytracer.scene.resolution.0;
-    let px_height = raytracer.scene.resolution.1;
+pub struct RenderOptions {
+    pub format: ImageFormat,
+    // EXR gets these as extra layers, other formats get the single requested
+    // pass instead of the shaded image
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    // transparent background, only for formats with an alpha channel
+    pub alpha: bool,
+    // return a false colour picture of what each pixel cost instead
+    pub heatmap: Option<CostMetric>,
+    // render progressively within this budget
+    pub budget: Option<Budget>,
+}
 
-    let pixel_map = raytracer.trace_rays();
+#[derive(Debug)]
+pub enum RenderError {
+    Output(OutputError),
+    // the token was cancelled before the image was finished
+    Cancelled,
+}
 
-    let mut image = Vec::new();
-    for color in pixel_map {
-        image.push((color.r * 255.0) as u8);
-        image.push((color.g * 255.0) as u8);
-        image.push((color.b * 255.0) as u8);
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Output(e) => write!(f, "{}", e),
+            RenderError::Cancelled => write!(f, "the render was cancelled"),
+        }
     }
-    let mut jpeg_bytes = Vec::new();
-    {
-        let writer = VecWriter::new(&mut jpeg_bytes);
-        let encoder = jpeg_encoder::Encoder::new(writer, 100);
-        encoder.encode(&image, px_width as u16, px_height as u16, jpeg_encoder::ColorType::Rgb).unwrap();
+}
+
+impl std::error::Error for RenderError {}
+
+impl From<OutputError> for RenderError {
+    fn from(e: OutputError) -> Self {
+        RenderError::Output(e)
     }
-    jpeg_bytes
 }
 
-fn normalize_colors(mut scene : Scene) -> Scene {
-    for material in &mut scene.materials {
-        material.diffuse = material.diffuse.normalize();
-        material.specular = material.specular.normalize();
+/// Renders `scene` and encodes it, along with the ray counts and the time
+/// each phase took. `timings` comes with the scene load filled in.
+/// Cancelling `token` stops tracing at the next row or tile, and the render
+/// fails with `RenderError::Cancelled`.
+pub fn handle_render(mut scene: Scene, options: &RenderOptions, mut timings: Timings, token: &CancellationToken) -> Result<(Vec<u8>, RenderStats), RenderError> {
+    let (format, aovs) = (options.format, &options.aovs);
+    scene.transparent_background |= options.alpha;
+    let alpha = scene.transparent_background && format.supports_alpha();
+    let mut raytracer = timed(&mut timings.setup, || raytracer::Raytracer::new(scene));
+    raytracer.cancellation = token.clone();
+    let raytracer = Arc::new(raytracer);
+    // a cancelled phase leaves part of its output black
+    let check = || if token.is_cancelled() { Err(RenderError::Cancelled) } else { Ok(()) };
+    let px_width = raytracer.scene.resolution.0;
+    let px_height = raytracer.scene.resolution.1;
+    let full = Region::full(raytracer.scene.resolution);
+
+    let (width, height) = (px_width as u32, px_height as u32);
+    let stats = |timings: Timings| RenderStats { rays: raytracer.counters.snapshot(), timings };
+
+    if !aovs.is_empty() && !matches!(format, ImageFormat::Exr(_)) {
+        let (_, buffers) = timed(&mut timings.tracing, || raytracer.trace_aovs(full, &aovs[..1]));
+        check()?;
+        let image = timed(&mut timings.encoding, || if format.is_hdr() {
+            output::encode(&buffers[0].pixels, width, height, format, &raytracer.scene.display)
+        } else {
+            output::encode(&buffers[0].visualize(), width, height, format, &DisplayTransform::default())
+        })?;
+        return Ok((image, stats(timings)));
     }
-    scene
-}
\ No newline at end of file
+
+    if let Some(metric) = options.heatmap {
+        let (_, _, _, heatmap) = timed(&mut timings.tracing, || heatmap::render(&raytracer, full, metric));
+        check()?;
+        println!("{}", heatmap);
+        let image = timed(&mut timings.encoding, || output::encode(&heatmap.false_color(), width, height, format, &DisplayTransform::default()))?;
+        return Ok((image, stats(timings)));
+    }
+
+    let (mut pixel_map, coverage) = timed(&mut timings.tracing, || match options.budget {
+        Some(budget) => {
+            let (_, accumulator) = progressive::render(&raytracer, full, budget, |_| {});
+            let mut pixel_map = accumulator.pixels;
+            raytracer.expose(&mut pixel_map);
+            (pixel_map, accumulator.coverage)
+        }
+        None if alpha => {
+            let (_, mut pixel_map, coverage) = raytracer.trace_region_covered(full);
+            raytracer.expose(&mut pixel_map);
+            (pixel_map, coverage)
+        }
+        None => (Arc::clone(&raytracer).trace_rays(), Vec::new()),
+    });
+    check()?;
+    timed(&mut timings.post_process, || {
+        if options.denoise {
+            match raytracer.denoise(full, &pixel_map, &Denoiser::default()) {
+                Ok(denoised) => pixel_map = denoised,
+                Err(e) => eprintln!("Error denoising, returning the noisy image: {}", e),
+            }
+        }
+        if let Some(bloom) = raytracer.scene.bloom {
+            if let Err(e) = bloom.apply(&mut pixel_map, px_width, px_height) {
+                eprintln!("Error adding bloom, returning the image without it: {}", e);
+            }
+        }
+    });
+    // denoising traces its guide passes
+    check()?;
+    let buffers = match format {
+        ImageFormat::Exr(_) if !aovs.is_empty() => timed(&mut timings.tracing, || raytracer.trace_aovs(full, aovs).1),
+        _ => Vec::new(),
+    };
+    check()?;
+    let image = timed(&mut timings.encoding, || match format {
+        ImageFormat::Exr(precision) if !buffers.is_empty() => {
+            aov::encode_exr(&pixel_map, alpha.then_some(&coverage[..]), &buffers, width, height, precision)
+        }
+        _ if alpha => output::encode_rgba(&pixel_map, &coverage, width, height, format, &raytracer.scene.display),
+        _ => output::encode(&pixel_map, width, height, format, &raytracer.scene.display),
+    })?;
+    Ok((image, stats(timings)))
+}
@@ -0,0 +1,278 @@
+use std::time::Duration;
+
+use rustracer_core::aov::Aov;
+use rustracer_core::graphics::tonemap::ToneMapper;
+use rustracer_core::heatmap::CostMetric;
+use rustracer_core::output::exr::ExrPrecision;
+use rustracer_core::output::{ImageFormat, OutputError};
+use rustracer_core::progressive::Budget;
+use rustracer_core::region::Region;
+use rustracer_core::sampling::SamplerKind;
+
+pub fn usage(program : &str) -> String {
+    format!("Usage: {0} [options] <scene file>
+       {0} upgrade <scene file>...
+
+Scene files are JSON, or TOML and YAML for .toml, .yaml and .yml files, or
+for .txt files the text format of the C++ raytracer.
+upgrade rewrites scene files written for older versions in the current layout.
+TOML and YAML files with comments are left for you to upgrade by hand.
+
+Options:
+    -o, --output file  where to write the image, the format is taken from the
+                       extension (default: the scene file name)
+    --format fmt       image format: jpg, png, ppm, tga, qoi, or exr and hdr
+                       for unclamped floating point output
+    --exr-float        write 32 bit instead of 16 bit floats to OpenEXR
+    --tonemap op       tone mapping for 8 bit formats: clamp, reinhard, filmic
+                       or aces (default: the scene's, or clamp)
+    --no-dither        don't dither when quantizing to 8 bits
+    --alpha            leave the background transparent, for png, tga, qoi
+                       and exr
+    --bloom            make bright parts of the image glow
+    --bloom-threshold l
+                       luminance above which pixels glow (default: 1.0)
+    --bloom-intensity i
+                       strength of the glow (default: 0.2)
+    --denoise          smooth out sampling noise, keeping edges found from the
+                       albedo, normals and depth
+    --crop x,y,w,h     only render the given window of the image
+    --tile-size n      render in n x n tiles instead of rows
+    --passes n         render progressively, averaging n passes of one sample
+                       per pixel and saving the image after each
+    --time-limit s     render progressively until s seconds have passed
+    --seed n           seed for the random numbers (default: the scene's, or 0)
+    --sampler name     where samples go: independent, stratified, halton or
+                       sobol (default: the scene's, or halton)
+    --frames a..b      render frames a to b (inclusive) of the scene's animation
+                       as numbered images
+    --heatmap metric   also write a false colour image of what each pixel cost,
+                       by time or intersection tests
+    --aov list         also render the comma separated passes depth, normal,
+                       albedo, material_index, object_index and position;
+                       layers of the same file for exr, otherwise one image
+                       each named after the pass", program)
+}
+
+pub struct Args {
+    pub scene_file : String,
+    pub crop : Option<Region>,
+    pub tile_size : Option<i32>,
+    pub frames : Option<(i32, i32)>,
+    pub seed : Option<u64>,
+    pub sampler : Option<SamplerKind>,
+    pub output : Option<String>,
+    pub format : Option<ImageFormat>,
+    pub exr_float : bool,
+    pub tone_mapper : Option<ToneMapper>,
+    pub no_dither : bool,
+    pub alpha : bool,
+    pub aovs : Vec<Aov>,
+    pub heatmap : Option<CostMetric>,
+    pub denoise : bool,
+    pub bloom : bool,
+    pub bloom_threshold : Option<f32>,
+    pub bloom_intensity : Option<f32>,
+    // set when rendering progressively
+    pub budget : Option<Budget>,
+}
+
+impl Args {
+    pub fn parse(args : &[String]) -> Result<Self, String> {
+        let mut scene_file = None;
+        let mut crop = None;
+        let mut tile_size = None;
+        let mut frames = None;
+        let mut seed = None;
+        let mut sampler = None;
+        let mut output = None;
+        let mut format = None;
+        let mut exr_float = false;
+        let mut tone_mapper = None;
+        let mut no_dither = false;
+        let mut alpha = false;
+        let mut aovs = Vec::new();
+        let mut heatmap = None;
+        let mut denoise = false;
+        let mut bloom = false;
+        let mut bloom_threshold = None;
+        let mut bloom_intensity = None;
+        let mut budget : Option<Budget> = None;
+
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--crop" => {
+                    let value = next_value(&mut iter, arg)?;
+                    crop = Some(Region::parse(value).ok_or(format!("invalid crop window: {}", value))?);
+                }
+                "--tile-size" => {
+                    let value = next_value(&mut iter, arg)?;
+                    match value.parse::<i32>() {
+                        Ok(n) if n > 0 => tile_size = Some(n),
+                        _ => return Err(format!("invalid tile size: {}", value)),
+                    }
+                }
+                "--frames" => {
+                    let value = next_value(&mut iter, arg)?;
+                    frames = Some(parse_frame_range(value).ok_or(format!("invalid frame range: {}", value))?);
+                }
+                "--seed" => {
+                    let value = next_value(&mut iter, arg)?;
+                    seed = Some(value.parse::<u64>().map_err(|_| format!("invalid seed: {}", value))?);
+                }
+                "--sampler" => {
+                    sampler = Some(next_value(&mut iter, arg)?.parse::<SamplerKind>()?);
+                }
+                "-o" | "--output" => {
+                    output = Some(next_value(&mut iter, arg)?.to_string());
+                }
+                "--format" => {
+                    let value = next_value(&mut iter, arg)?;
+                    format = Some(value.parse::<ImageFormat>().map_err(|e| e.to_string())?);
+                }
+                "--exr-float" => exr_float = true,
+                "--tonemap" => {
+                    tone_mapper = Some(next_value(&mut iter, arg)?.parse::<ToneMapper>()?);
+                }
+                "--no-dither" => no_dither = true,
+                "--alpha" => alpha = true,
+                "--denoise" => denoise = true,
+                "--bloom" => bloom = true,
+                "--bloom-threshold" => {
+                    bloom_threshold = Some(parse_non_negative(next_value(&mut iter, arg)?, "bloom threshold")?);
+                }
+                "--bloom-intensity" => {
+                    bloom_intensity = Some(parse_non_negative(next_value(&mut iter, arg)?, "bloom intensity")?);
+                }
+                "--passes" => {
+                    let value = next_value(&mut iter, arg)?;
+                    match value.parse::<u32>() {
+                        Ok(n) if n > 0 => budget.get_or_insert_with(Budget::default).passes = Some(n),
+                        _ => return Err(format!("invalid number of passes: {}", value)),
+                    }
+                }
+                "--time-limit" => {
+                    let value = next_value(&mut iter, arg)?;
+                    match value.parse::<f32>() {
+                        Ok(s) if s > 0.0 && s.is_finite() => budget.get_or_insert_with(Budget::default).time = Some(Duration::from_secs_f32(s)),
+                        _ => return Err(format!("invalid time limit: {}", value)),
+                    }
+                }
+                "--heatmap" => {
+                    heatmap = Some(next_value(&mut iter, arg)?.parse::<CostMetric>()?);
+                }
+                "--aov" => {
+                    aovs = Aov::parse_list(next_value(&mut iter, arg)?)?;
+                }
+                _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+                _ => {
+                    if scene_file.is_some() {
+                        return Err(format!("unexpected argument: {}", arg));
+                    }
+                    scene_file = Some(arg.clone());
+                }
+            }
+        }
+
+        if heatmap.is_some() && budget.is_some() {
+            return Err("--heatmap can't be combined with progressive rendering".to_string());
+        }
+
+        Ok(Args {
+            scene_file : scene_file.ok_or("missing scene file")?,
+            crop,
+            tile_size,
+            frames,
+            seed,
+            sampler,
+            output,
+            format,
+            exr_float,
+            tone_mapper,
+            no_dither,
+            alpha,
+            aovs,
+            heatmap,
+            denoise,
+            bloom,
+            bloom_threshold,
+            bloom_intensity,
+            budget,
+        })
+    }
+
+    /// The output format: `--format` if given, then the extension of
+    /// `--output`, falling back to JPEG.
+    pub fn image_format(&self) -> Result<ImageFormat, String> {
+        let format = match (self.format, &self.output) {
+            (Some(format), _) => format,
+            (None, Some(output)) => ImageFormat::from_path(output).ok_or(format!("can't tell the image format of {}, use --format", output))?,
+            (None, None) => ImageFormat::Jpeg,
+        };
+        if self.alpha && !format.supports_alpha() {
+            return Err(OutputError::NoAlpha(format).to_string());
+        }
+        match format {
+            ImageFormat::Exr(_) if self.exr_float => Ok(ImageFormat::Exr(ExrPrecision::Float)),
+            _ => Ok(format),
+        }
+    }
+
+    /// Where to write the image, with the frame number added before the
+    /// extension when rendering a sequence.
+    pub fn output_file(&self, format : ImageFormat, frame : Option<i32>) -> String {
+        let path = match &self.output {
+            Some(output) => output.clone(),
+            None => {
+                let stem = self.scene_file.strip_suffix(".json").unwrap_or(&self.scene_file);
+                format!("{}.{}", stem, format.extension())
+            }
+        };
+        match frame {
+            Some(frame) => with_suffix(&path, &format!("{:04}", frame)),
+            None => path,
+        }
+    }
+
+    /// Where to write the cost heatmap.
+    pub fn heatmap_file(&self, output_file : &str) -> String {
+        with_suffix(output_file, "heatmap")
+    }
+
+    /// Where to write a pass when the format can't hold it as a layer.
+    pub fn aov_file(&self, output_file : &str, aov : Aov) -> String {
+        with_suffix(output_file, aov.name())
+    }
+}
+
+// adds `_suffix` before the extension
+fn with_suffix(path : &str, suffix : &str) -> String {
+    match path.rfind('.') {
+        Some(dot) if !path[dot..].contains('/') => format!("{}_{}{}", &path[..dot], suffix, &path[dot..]),
+        _ => format!("{}_{}", path, suffix),
+    }
+}
+
+fn next_value<'a>(iter : &mut impl Iterator<Item = &'a String>, option : &str) -> Result<&'a str, String> {
+    iter.next()
+        .map(|s| s.as_str())
+        .ok_or(format!("{} needs a value", option))
+}
+
+fn parse_non_negative(s : &str, what : &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(v) if v >= 0.0 && v.is_finite() => Ok(v),
+        _ => Err(format!("invalid {}: {}", what, s)),
+    }
+}
+
+fn parse_frame_range(s : &str) -> Option<(i32, i32)> {
+    let (start, end) = s.split_once("..")?;
+    let start = start.trim().parse::<i32>().ok()?;
+    let end = end.trim().parse::<i32>().ok()?;
+    if end < start {
+        return None;
+    }
+    Some((start, end))
+}
@@ -1,41 +1,223 @@
+mod args;
+
 use std::env;
-use std::sync::Arc;
 
+use args::Args;
+use rustracer_core::aov;
+use rustracer_core::denoise::Denoiser;
+use rustracer_core::graphics::bloom::Bloom;
+use rustracer_core::graphics::color::Color;
+use rustracer_core::graphics::tonemap::DisplayTransform;
+use rustracer_core::heatmap;
+use rustracer_core::migration::CURRENT_VERSION;
+use rustracer_core::output::{self, ImageFormat, OutputError};
+use rustracer_core::progressive;
 use rustracer_core::raytracer;
+use rustracer_core::region::Region;
 use rustracer_core::scene::Scene;
-use jpeg_encoder;
-use wavefront::Obj;
+use rustracer_core::stats::{timed, RenderStats, Timings};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        eprintln!("Usage: {} <scene file>", args[0]);
-        std::process::exit(1);
+    if args.get(1).is_some_and(|command| command == "upgrade") {
+        upgrade(&args[2..]);
+        return;
     }
-    let filename = &args[1];
-
-    let scene: Scene = Scene::from_file(filename);
-    
+    let options = match Args::parse(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, args::usage(&args[0]));
+            std::process::exit(1);
+        }
+    };
+    let format = match options.image_format() {
+        Ok(format) => format,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let filename = &options.scene_file;
 
+    let mut timings = Timings::default();
+    let scene = match timed(&mut timings.scene_load, || Scene::load(filename)) {
+        Ok(scene) => scene,
+        Err(e) => {
+            eprintln!("Error loading scene file {}: {}", filename, e);
+            std::process::exit(1);
+        }
+    };
     println!("Successfully loaded scene from {}", filename);
 
-    let raytracer = Arc::new(raytracer::Raytracer::new(scene));
-    let px_width = raytracer.scene.resolution.0;
-    let px_height = raytracer.scene.resolution.1;
+    match options.frames {
+        Some((start, end)) => {
+            for frame in start..=end {
+                println!("rendering frame {}...", frame);
+                render(scene.at_frame(frame as f32), &options, format, &options.output_file(format, Some(frame)), timings);
+            }
+        }
+        None => render(scene, &options, format, &options.output_file(format, None), timings),
+    }
+}
+
+// `timings` comes with the scene load filled in
+fn render(mut scene : Scene, options : &Args, format : ImageFormat, filename : &str, mut timings : Timings) {
+    if let Some(tone_mapper) = options.tone_mapper {
+        scene.display.tone_mapper = tone_mapper;
+    }
+    if options.no_dither {
+        scene.display.dither = false;
+    }
+    if options.alpha {
+        scene.transparent_background = true;
+    }
+    if let Some(seed) = options.seed {
+        scene.seed = seed;
+    }
+    if let Some(sampler) = options.sampler {
+        scene.sampler = sampler;
+    }
+    if options.bloom || options.bloom_threshold.is_some() || options.bloom_intensity.is_some() {
+        let bloom = scene.bloom.get_or_insert_with(Bloom::default);
+        if let Some(threshold) = options.bloom_threshold {
+            bloom.threshold = threshold;
+        }
+        if let Some(intensity) = options.bloom_intensity {
+            bloom.intensity = intensity;
+        }
+    }
+    let raytracer = timed(&mut timings.setup, || raytracer::Raytracer::new(scene));
+    let region = options.crop.unwrap_or(Region::full(raytracer.scene.resolution));
+    let alpha = raytracer.scene.transparent_background && format.supports_alpha();
+    if raytracer.scene.transparent_background && !alpha {
+        eprintln!("{} images have no alpha channel, the background will be black", format);
+    }
+    // crops are dithered like the same pixels of the full image
+    let image_width = raytracer.scene.resolution.0;
+    let write = |filename : &str, pixels : &[Color], coverage : &[f32], region : Region| -> Result<(), OutputError> {
+        let bytes = if alpha {
+            output::encode_crop_rgba(pixels, coverage, region, image_width, format, &raytracer.scene.display)?
+        } else {
+            output::encode_crop(pixels, region, image_width, format, &raytracer.scene.display)?
+        };
+        std::fs::write(filename, bytes)?;
+        Ok(())
+    };
+
+    println!("tracing rays...");
+    let mut heatmap = None;
+    let (region, mut pixel_map, coverage) = timed(&mut timings.tracing, || match (options.budget, options.tile_size, options.heatmap) {
+        (None, _, Some(metric)) => {
+            let (region, pixel_map, coverage, costs) = heatmap::render(&raytracer, region, metric);
+            heatmap = Some(costs);
+            (region, pixel_map, coverage)
+        }
+        (Some(budget), _, _) => {
+            let (region, accumulator) = progressive::render(&raytracer, region, budget, |progress| {
+                if progress.region.is_empty() {
+                    return;
+                }
+                println!("pass {} done after {:.1}s", progress.passes, progress.elapsed.as_secs_f32());
+                let mut preview = progress.pixels.to_vec();
+                raytracer.expose(&mut preview);
+                if let Err(e) = write(filename, &preview, progress.coverage, progress.region) {
+                    eprintln!("Error saving {}: {}", filename, e);
+                }
+            });
+            (region, accumulator.pixels, accumulator.coverage)
+        }
+        // tiles only change how work is handed out, coverage comes by rows
+        (None, _, None) if alpha => raytracer.trace_region_covered(region),
+        (None, Some(tile_size), None) => {
+            let (region, pixel_map) = raytracer.trace_region_tiled(region, tile_size);
+            (region, pixel_map, Vec::new())
+        }
+        (None, None, None) => {
+            let (region, pixel_map) = raytracer.trace_region(region);
+            (region, pixel_map, Vec::new())
+        }
+    });
+    println!("tracing complete.");
+    if region.is_empty() {
+        eprintln!("Crop window lies outside the {}x{} image", raytracer.scene.resolution.0, raytracer.scene.resolution.1);
+        std::process::exit(1);
+    }
+    timed(&mut timings.post_process, || {
+        raytracer.expose(&mut pixel_map);
+        if options.denoise {
+            println!("denoising...");
+            match raytracer.denoise(region, &pixel_map, &Denoiser::default()) {
+                Ok(denoised) => pixel_map = denoised,
+                Err(e) => eprintln!("Error denoising, keeping the noisy image: {}", e),
+            }
+        }
+        if let Some(bloom) = raytracer.scene.bloom {
+            if let Err(e) = bloom.apply(&mut pixel_map, region.width, region.height) {
+                eprintln!("Error adding bloom, keeping the image without it: {}", e);
+            }
+        }
+    });
+    let aovs = if options.aovs.is_empty() {
+        Vec::new()
+    } else {
+        timed(&mut timings.tracing, || raytracer.trace_aovs(region, &options.aovs).1)
+    };
+    let (width, height) = (region.width as u32, region.height as u32);
 
-    let pixel_map = raytracer.trace_rays();
-    
+    timed(&mut timings.encoding, || match format {
+        _ if aovs.is_empty() => save(filename, write(filename, &pixel_map, &coverage, region)),
+        ImageFormat::Exr(precision) => {
+            let result = aov::encode_exr(&pixel_map, alpha.then_some(&coverage[..]), &aovs, width, height, precision)
+                .and_then(|bytes| Ok(std::fs::write(filename, bytes)?));
+            save(filename, result);
+        }
+        _ => {
+            save(filename, write(filename, &pixel_map, &coverage, region));
+            for buffer in &aovs {
+                let aov_file = options.aov_file(filename, buffer.aov);
+                let result = if format.is_hdr() {
+                    output::write_image(&aov_file, &buffer.pixels, width, height, format, &raytracer.scene.display)
+                } else {
+                    output::write_image(&aov_file, &buffer.visualize(), width, height, format, &DisplayTransform::default())
+                };
+                save(&aov_file, result);
+            }
+        }
+    });
+    if let Some(heatmap) = heatmap {
+        println!("{}", heatmap);
+        let heatmap_file = options.heatmap_file(filename);
+        let result = timed(&mut timings.encoding, || output::write_image(&heatmap_file, &heatmap.false_color(), width, height, format, &DisplayTransform::default()));
+        save(&heatmap_file, result);
+    }
+    println!("{}", RenderStats { rays : raytracer.counters.snapshot(), timings });
+}
 
-    let filename = &args[1];
-    let filename = filename.replace(".json", ".jpg");
+fn upgrade(filenames : &[String]) {
+    if filenames.is_empty() {
+        eprintln!("upgrade needs at least one scene file");
+        std::process::exit(1);
+    }
+    let mut failed = false;
+    for filename in filenames {
+        match Scene::upgrade_file(filename) {
+            Ok(version) if version == CURRENT_VERSION => println!("{} is already at version {}", filename, version),
+            Ok(version) => println!("upgraded {} from version {} to {}", filename, version, CURRENT_VERSION),
+            Err(e) => {
+                eprintln!("Error upgrading {}: {}", filename, e);
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
 
-    let mut image = Vec::new();
-    for color in pixel_map {
-        image.push((color.r * 255.0) as u8);
-        image.push((color.g * 255.0) as u8);
-        image.push((color.b * 255.0) as u8);
+fn save(filename : &str, result : Result<(), output::OutputError>) {
+    if let Err(e) = result {
+        eprintln!("Error saving {}: {}", filename, e);
+        std::process::exit(1);
     }
-    let encoder = jpeg_encoder::Encoder::new_file(&filename, 100).unwrap();
-    encoder.encode(&image, px_width as u16, px_height as u16, jpeg_encoder::ColorType::Rgb).unwrap();
     println!("Image saved to {}", filename);
-}
\ No newline at end of file
+}
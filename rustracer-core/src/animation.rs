@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::color::Color;
+use crate::math::vector::Vector;
+use crate::scene::Scene;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    // Catmull-Rom through the keyframes
+    Spline,
+}
+
+/// Values that can be blended between keyframes.
+pub trait Animatable: Copy {
+    fn lerp(a : Self, b : Self, t : f32) -> Self;
+    fn catmull_rom(p0 : Self, p1 : Self, p2 : Self, p3 : Self, t : f32) -> Self;
+}
+
+fn catmull_rom(p0 : f32, p1 : f32, p2 : f32, p3 : f32, t : f32) -> f32 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    0.5 * ((2.0 * p1)
+        + (-p0 + p2) * t
+        + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t2
+        + (-p0 + 3.0 * p1 - 3.0 * p2 + p3) * t3)
+}
+
+impl Animatable for f32 {
+    fn lerp(a : Self, b : Self, t : f32) -> Self {
+        a + (b - a) * t
+    }
+
+    fn catmull_rom(p0 : Self, p1 : Self, p2 : Self, p3 : Self, t : f32) -> Self {
+        catmull_rom(p0, p1, p2, p3, t)
+    }
+}
+
+// w is taken from the keyframe we are leaving so points stay points
+impl Animatable for Vector {
+    fn lerp(a : Self, b : Self, t : f32) -> Self {
+        Vector::new(f32::lerp(a.x, b.x, t), f32::lerp(a.y, b.y, t), f32::lerp(a.z, b.z, t), a.w)
+    }
+
+    fn catmull_rom(p0 : Self, p1 : Self, p2 : Self, p3 : Self, t : f32) -> Self {
+        Vector::new(
+            catmull_rom(p0.x, p1.x, p2.x, p3.x, t),
+            catmull_rom(p0.y, p1.y, p2.y, p3.y, t),
+            catmull_rom(p0.z, p1.z, p2.z, p3.z, t),
+            p1.w
+        )
+    }
+}
+
+impl Animatable for Color {
+    fn lerp(a : Self, b : Self, t : f32) -> Self {
+        Color::new(f32::lerp(a.r, b.r, t), f32::lerp(a.g, b.g, t), f32::lerp(a.b, b.b, t))
+    }
+
+    fn catmull_rom(p0 : Self, p1 : Self, p2 : Self, p3 : Self, t : f32) -> Self {
+        Color::new(
+            catmull_rom(p0.r, p1.r, p2.r, p3.r, t),
+            catmull_rom(p0.g, p1.g, p2.g, p3.g, t),
+            catmull_rom(p0.b, p1.b, p2.b, p3.b, t)
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    pub frame : f32,
+    pub value : T,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "UnsortedTrack<T>")]
+pub struct Track<T> {
+    // sorted by frame
+    pub keyframes : Vec<Keyframe<T>>,
+    #[serde(default)]
+    pub interpolation : Interpolation,
+}
+
+// a track as written in the scene, before its keyframes are sorted
+#[derive(Deserialize)]
+struct UnsortedTrack<T> {
+    keyframes : Vec<Keyframe<T>>,
+    #[serde(default)]
+    interpolation : Interpolation,
+}
+
+impl<T> From<UnsortedTrack<T>> for Track<T> {
+    fn from(track : UnsortedTrack<T>) -> Self {
+        let mut keyframes = track.keyframes;
+        keyframes.sort_by(|a, b| a.frame.total_cmp(&b.frame));
+        Track {
+            keyframes,
+            interpolation : track.interpolation
+        }
+    }
+}
+
+impl<T : Animatable> Track<T> {
+    pub fn new(keyframes : Vec<Keyframe<T>>, interpolation : Interpolation) -> Self {
+        UnsortedTrack { keyframes, interpolation }.into()
+    }
+
+    /// The value of the track at `frame`. Frames outside the keyed range hold
+    /// the first or last value.
+    pub fn sample(&self, frame : f32) -> Option<T> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if frame <= first.frame {
+            return Some(first.value);
+        }
+        if frame >= last.frame {
+            return Some(last.value);
+        }
+
+        // index of the keyframe at the end of the segment containing frame
+        let i = keys.iter().position(|k| k.frame > frame)?;
+        let (k1, k2) = (&keys[i - 1], &keys[i]);
+        let t = (frame - k1.frame) / (k2.frame - k1.frame);
+        match self.interpolation {
+            Interpolation::Linear => Some(T::lerp(k1.value, k2.value, t)),
+            Interpolation::Spline => {
+                let p0 = if i >= 2 { keys[i - 2].value } else { k1.value };
+                let p3 = if i + 1 < keys.len() { keys[i + 1].value } else { k2.value };
+                Some(T::catmull_rom(p0, k1.value, k2.value, p3, t))
+            }
+        }
+    }
+}
+
+/// A track driving one element of a list in the scene, e.g. a sphere.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IndexedTrack<T> {
+    pub index : usize,
+    #[serde(flatten)]
+    pub track : Track<T>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct Animation {
+    #[serde(default)]
+    pub eye_pos : Option<Track<Vector>>,
+    #[serde(default)]
+    pub view_dir : Option<Track<Vector>>,
+    #[serde(default)]
+    pub sphere_centers : Vec<IndexedTrack<Vector>>,
+    #[serde(default)]
+    pub light_positions : Vec<IndexedTrack<Vector>>,
+    // diffuse colour of the material
+    #[serde(default)]
+    pub material_colors : Vec<IndexedTrack<Color>>,
+}
+
+impl Animation {
+    /// Overwrites the animated values of `scene` with their values at `frame`.
+    /// Tracks pointing at elements the scene does not have are ignored.
+    pub fn apply(&self, scene : &mut Scene, frame : f32) {
+        if let Some(eye_pos) = self.eye_pos.as_ref().and_then(|t| t.sample(frame)) {
+            scene.eye_pos = eye_pos;
+        }
+        if let Some(mut view_dir) = self.view_dir.as_ref().and_then(|t| t.sample(frame)) {
+            view_dir.normalize();
+            scene.view_dir = view_dir;
+        }
+        for track in &self.sphere_centers {
+            if let (Some(sphere), Some(center)) = (scene.spheres.get_mut(track.index), track.track.sample(frame)) {
+                sphere.center = center;
+            }
+        }
+        for track in &self.light_positions {
+            if let (Some(light), Some(v)) = (scene.lights.get_mut(track.index), track.track.sample(frame)) {
+                light.v = v;
+            }
+        }
+        for track in &self.material_colors {
+            if let (Some(material), Some(color)) = (scene.materials.get_mut(track.index), track.track.sample(frame)) {
+                material.diffuse = color;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(interpolation : Interpolation) -> Track<f32> {
+        Track::new(vec![
+            Keyframe { frame : 10.0, value : 1.0 },
+            Keyframe { frame : 0.0, value : 0.0 },
+            Keyframe { frame : 20.0, value : 4.0 },
+        ], interpolation)
+    }
+
+    #[test]
+    fn test_linear_track() {
+        let track = track(Interpolation::Linear);
+        assert_eq!(track.sample(-5.0), Some(0.0));
+        assert_eq!(track.sample(5.0), Some(0.5));
+        assert_eq!(track.sample(15.0), Some(2.5));
+        assert_eq!(track.sample(25.0), Some(4.0));
+    }
+
+    #[test]
+    fn test_spline_track_passes_through_keyframes() {
+        let track = track(Interpolation::Spline);
+        assert_eq!(track.sample(0.0), Some(0.0));
+        assert_eq!(track.sample(10.0), Some(1.0));
+        assert_eq!(track.sample(20.0), Some(4.0));
+        let mid = track.sample(15.0).unwrap();
+        assert!(mid > 1.0 && mid < 4.0);
+    }
+
+    #[test]
+    fn test_empty_track() {
+        let track : Track<f32> = Track::new(Vec::new(), Interpolation::Linear);
+        assert_eq!(track.sample(1.0), None);
+    }
+
+    #[test]
+    fn test_vector_lerp_keeps_points() {
+        let a = Vector::new(0.0, 0.0, 0.0, 1.0);
+        let b = Vector::new(2.0, 4.0, 6.0, 1.0);
+        let p = Vector::lerp(a, b, 0.5);
+        assert_eq!((p.x, p.y, p.z, p.w), (1.0, 2.0, 3.0, 1.0));
+    }
+
+    #[test]
+    fn test_animation_from_json() {
+        let animation : Animation = serde_json::from_str(r#"{
+            "sphere_centers": [{
+                "index": 0,
+                "interpolation": "spline",
+                "keyframes": [
+                    {"frame": 0, "value": {"x": 0.0, "y": 0.0, "z": -8.0, "w": 1.0}},
+                    {"frame": 10, "value": {"x": 4.0, "y": 0.0, "z": -8.0, "w": 1.0}}
+                ]
+            }]
+        }"#).unwrap();
+        assert!(animation.eye_pos.is_none());
+        assert_eq!(animation.sphere_centers[0].track.interpolation, Interpolation::Spline);
+        assert_eq!(animation.sphere_centers[0].track.sample(10.0).unwrap().x, 4.0);
+    }
+
+    #[test]
+    fn test_keyframes_are_sorted_on_load() {
+        let track : IndexedTrack<f32> = serde_json::from_str(r#"{
+            "index": 0,
+            "keyframes": [
+                {"frame": 10, "value": 1.0},
+                {"frame": 0, "value": 0.0}
+            ]
+        }"#).unwrap();
+        assert_eq!(track.track.keyframes[0].frame, 0.0);
+        assert_eq!(track.track.sample(5.0), Some(0.5));
+    }
+}
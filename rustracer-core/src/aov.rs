@@ -0,0 +1,202 @@
+use core::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::color::Color;
+use crate::graphics::tonemap::srgb_decode;
+use crate::output::exr::{self, ExrLayer, ExrPrecision};
+use crate::output::OutputError;
+use crate::raytracer::Hit;
+
+/// Arbitrary output variables, auxiliary passes rendered next to the shaded
+/// image for compositing and debugging.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Aov {
+    // distance from the eye along the view direction
+    Depth,
+    // world space surface normal
+    Normal,
+    // diffuse material colour
+    Albedo,
+    MaterialIndex,
+    ObjectIndex,
+    // world space hit position
+    Position,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 6] = [Aov::Depth, Aov::Normal, Aov::Albedo, Aov::MaterialIndex, Aov::ObjectIndex, Aov::Position];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::MaterialIndex => "material_index",
+            Aov::ObjectIndex => "object_index",
+            Aov::Position => "position",
+        }
+    }
+
+    /// Whether the pass has one value per pixel rather than three.
+    pub fn is_scalar(&self) -> bool {
+        matches!(self, Aov::Depth | Aov::MaterialIndex | Aov::ObjectIndex)
+    }
+
+    /// The value written for a ray that hits nothing.
+    pub fn background(&self) -> Color {
+        match self {
+            Aov::Depth => Color::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            Aov::MaterialIndex | Aov::ObjectIndex => Color::new(-1.0, -1.0, -1.0),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// The value of this pass for `hit`. `albedo` is the diffuse colour of the
+    /// material that was hit.
+    pub fn value(&self, hit : &Hit, depth : f32, albedo : Color) -> Color {
+        match self {
+            Aov::Depth => Color::new(depth, depth, depth),
+            Aov::Normal => Color::new(hit.normal.x, hit.normal.y, hit.normal.z),
+            Aov::Albedo => albedo,
+            Aov::MaterialIndex => {
+                let i = hit.material_index as f32;
+                Color::new(i, i, i)
+            }
+            Aov::ObjectIndex => {
+                let i = hit.object_index as f32;
+                Color::new(i, i, i)
+            }
+            Aov::Position => Color::new(hit.point.x, hit.point.y, hit.point.z),
+        }
+    }
+
+    /// Parses a comma separated list such as `depth,normal`.
+    pub fn parse_list(s : &str) -> Result<Vec<Aov>, String> {
+        s.split(',').map(|name| name.trim().parse::<Aov>()).collect()
+    }
+}
+
+impl FromStr for Aov {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        Aov::ALL.iter()
+            .find(|aov| aov.name() == s)
+            .copied()
+            .ok_or(format!("unknown AOV: {}", s))
+    }
+}
+
+impl fmt::Display for Aov {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// a bright, well spread colour for each index
+fn index_color(i : f32) -> Color {
+    if i < 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let hue = (i * 0.618034).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    match hue as i32 {
+        0 => Color::new(1.0, x, 0.0),
+        1 => Color::new(x, 1.0, 0.0),
+        2 => Color::new(0.0, 1.0, x),
+        3 => Color::new(0.0, x, 1.0),
+        4 => Color::new(x, 0.0, 1.0),
+        _ => Color::new(1.0, 0.0, x),
+    }
+}
+
+/// One rendered pass, row-major. Scalar passes repeat their value in all
+/// three channels.
+#[derive(Clone, Debug)]
+pub struct AovBuffer {
+    pub aov : Aov,
+    pub pixels : Vec<Color>,
+}
+
+impl AovBuffer {
+    pub fn exr_layer(&self) -> ExrLayer {
+        if self.aov.is_scalar() {
+            let channel = if self.aov == Aov::Depth { "Z" } else { "Y" };
+            ExrLayer::single(self.aov.name(), channel, self.pixels.iter().map(|c| c.r).collect())
+        } else {
+            ExrLayer::rgb(self.aov.name(), &self.pixels)
+        }
+    }
+
+    /// A picture of the pass for 8 bit formats: depth is scaled to the
+    /// farthest hit, normals map -1..1 to 0..1 and indices get a colour each.
+    /// The values are linear, ready for the usual display transform.
+    pub fn visualize(&self) -> Vec<Color> {
+        let display = |c : Color| Color::new(srgb_decode(c.r.clamp(0.0, 1.0)), srgb_decode(c.g.clamp(0.0, 1.0)), srgb_decode(c.b.clamp(0.0, 1.0)));
+        match self.aov {
+            Aov::Depth => {
+                let far = self.pixels.iter()
+                    .map(|c| c.r)
+                    .filter(|d| d.is_finite())
+                    .fold(0.0, f32::max);
+                self.pixels.iter().map(|c| {
+                    // near is bright, the background black
+                    let v = if c.r.is_finite() && far > 0.0 { 1.0 - c.r / far * 0.9 } else { 0.0 };
+                    display(Color::new(v, v, v))
+                }).collect()
+            }
+            Aov::Normal => self.pixels.iter()
+                .map(|c| display(Color::new(c.r * 0.5 + 0.5, c.g * 0.5 + 0.5, c.b * 0.5 + 0.5)))
+                .collect(),
+            Aov::MaterialIndex | Aov::ObjectIndex => self.pixels.iter().map(|c| index_color(c.r)).collect(),
+            Aov::Albedo | Aov::Position => self.pixels.clone(),
+        }
+    }
+}
+
+/// A multi-part OpenEXR file holding the beauty image, with an alpha channel
+/// if `coverage` is given, and every pass.
+pub fn encode_exr(beauty : &[Color], coverage : Option<&[f32]>, aovs : &[AovBuffer], width : u32, height : u32, precision : ExrPrecision) -> Result<Vec<u8>, OutputError> {
+    let beauty = match coverage {
+        Some(alpha) => ExrLayer::rgba("", beauty, alpha),
+        None => ExrLayer::rgb("", beauty),
+    };
+    let mut layers = vec![beauty];
+    layers.extend(aovs.iter().map(AovBuffer::exr_layer));
+    exr::encode_layers(&layers, width, height, precision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_aovs() {
+        assert_eq!(Aov::parse_list("depth, normal,object_index").unwrap(), vec![Aov::Depth, Aov::Normal, Aov::ObjectIndex]);
+        assert!(Aov::parse_list("depth,motion").is_err());
+    }
+
+    #[test]
+    fn test_exr_layer_channels() {
+        let depth = AovBuffer { aov : Aov::Depth, pixels : vec![Color::new(2.0, 2.0, 2.0)] };
+        let layer = depth.exr_layer();
+        assert_eq!(layer.name, "depth");
+        assert_eq!(layer.channels.len(), 1);
+        assert_eq!(layer.channels[0].0, "Z");
+        let normal = AovBuffer { aov : Aov::Normal, pixels : vec![Color::new(0.0, 1.0, 0.0)] };
+        assert_eq!(normal.exr_layer().channels.len(), 3);
+    }
+
+    #[test]
+    fn test_visualize_depth() {
+        let inf = f32::INFINITY;
+        let depth = AovBuffer { aov : Aov::Depth, pixels : vec![Color::new(1.0, 1.0, 1.0), Color::new(10.0, 10.0, 10.0), Color::new(inf, inf, inf)] };
+        let v = depth.visualize();
+        assert!(v[0].r > v[1].r);
+        assert!(v[1].r > 0.0);
+        assert_eq!(v[2].r, 0.0);
+    }
+}
@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::color::Color;
+
+// middle grey, what auto exposure maps the average luminance to
+const DEFAULT_KEY : f32 = 0.18;
+
+/// Camera exposure settings, either from physical camera controls or picked
+/// automatically from the rendered image. The default camera (ISO 100, 1 s,
+/// f/1) is EV100 0, which scales radiance by 1 / 1.2 rather than leaving it
+/// unchanged.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Exposure {
+    #[serde(default = "default_iso")]
+    pub iso : f32,
+    // seconds
+    #[serde(default = "default_shutter_speed")]
+    pub shutter_speed : f32,
+    #[serde(default = "default_f_stop")]
+    pub f_stop : f32,
+    // exposure value at ISO 100, replaces iso, shutter_speed and f_stop if set
+    #[serde(default)]
+    pub ev : Option<f32>,
+    #[serde(default)]
+    pub auto : bool,
+    #[serde(default = "default_key")]
+    pub key : f32,
+    // extra stops applied on top of either mode
+    #[serde(default)]
+    pub compensation : f32,
+}
+
+fn default_iso() -> f32 {
+    100.0
+}
+
+fn default_shutter_speed() -> f32 {
+    1.0
+}
+
+fn default_f_stop() -> f32 {
+    1.0
+}
+
+fn default_key() -> f32 {
+    DEFAULT_KEY
+}
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Exposure {
+            iso : default_iso(),
+            shutter_speed : default_shutter_speed(),
+            f_stop : default_f_stop(),
+            ev : None,
+            auto : false,
+            key : DEFAULT_KEY,
+            compensation : 0.0,
+        }
+    }
+}
+
+impl Exposure {
+    /// EV100 from the camera controls.
+    pub fn ev100(&self) -> f32 {
+        match self.ev {
+            Some(ev) => ev,
+            None => ((self.f_stop * self.f_stop) / self.shutter_speed * 100.0 / self.iso).log2(),
+        }
+    }
+
+    /// The factor linear radiance is multiplied by. `pixels` is only looked
+    /// at in auto mode.
+    pub fn scale(&self, pixels : &[Color]) -> f32 {
+        let scale = if self.auto {
+            self.key / log_average_luminance(pixels)
+        } else {
+            // saturation based sensitivity, 1.2 is the ISO 2720 calibration
+            // constant for a 78% reflectance highlight
+            1.0 / (1.2 * 2.0_f32.powf(self.ev100()))
+        };
+        scale * 2.0_f32.powf(self.compensation)
+    }
+
+    pub fn apply(&self, pixels : &mut [Color]) {
+        let scale = self.scale(pixels);
+        for color in pixels.iter_mut() {
+            *color = *color * scale;
+        }
+    }
+}
+
+/// Geometric mean of the luminance, which is not thrown off by a few very
+/// bright pixels the way the arithmetic mean is.
+pub fn log_average_luminance(pixels : &[Color]) -> f32 {
+    if pixels.is_empty() {
+        return DEFAULT_KEY;
+    }
+    let delta = 1e-4;
+    let sum : f32 = pixels.iter().map(|c| (delta + c.luminance().max(0.0)).ln()).sum();
+    (sum / pixels.len() as f32).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ev100() {
+        let exposure = Exposure { f_stop : 16.0, shutter_speed : 1.0 / 100.0, iso : 100.0, ..Exposure::default() };
+        // sunny 16 rule
+        assert!((exposure.ev100() - 14.64).abs() < 0.01);
+        let exposure = Exposure { ev : Some(3.0), ..exposure };
+        assert_eq!(exposure.ev100(), 3.0);
+    }
+
+    #[test]
+    fn test_one_stop_halves_scale() {
+        let exposure = Exposure { ev : Some(1.0), ..Exposure::default() };
+        let brighter = Exposure { ev : Some(0.0), ..Exposure::default() };
+        assert!((brighter.scale(&[]) / exposure.scale(&[]) - 2.0).abs() < 1e-5);
+        let compensated = Exposure { compensation : 1.0, ..exposure };
+        assert!((compensated.scale(&[]) - brighter.scale(&[])).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_auto_exposure_maps_average_to_key() {
+        let exposure = Exposure { auto : true, ..Exposure::default() };
+        let mut pixels = vec![Color::new(2.0, 2.0, 2.0); 4];
+        exposure.apply(&mut pixels);
+        assert!((pixels[0].luminance() - DEFAULT_KEY).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+/// Brown-Conrady radial (`k1`..`k3`) and tangential (`p1`, `p2`) distortion,
+/// in the same convention as OpenCV so calibrated coefficients can be copied
+/// over. Coordinates are normalized so the image spans -1.0 to 1.0
+/// horizontally.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Distortion {
+    #[serde(default)]
+    pub k1 : f32,
+    #[serde(default)]
+    pub k2 : f32,
+    #[serde(default)]
+    pub k3 : f32,
+    #[serde(default)]
+    pub p1 : f32,
+    #[serde(default)]
+    pub p2 : f32,
+}
+
+impl Distortion {
+    pub fn is_identity(&self) -> bool {
+        *self == Distortion::default()
+    }
+
+    /// Where an undistorted point ends up in the image.
+    pub fn distort(&self, x : f32, y : f32) -> (f32, f32) {
+        let r2 = x * x + y * y;
+        let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+        let xd = x * radial + 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+        let yd = y * radial + self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+        (xd, yd)
+    }
+
+    /// Inverse of `distort`, found by fixed point iteration.
+    pub fn undistort(&self, xd : f32, yd : f32) -> (f32, f32) {
+        let (mut x, mut y) = (xd, yd);
+        for _ in 0..20 {
+            let r2 = x * x + y * y;
+            let radial = 1.0 + r2 * (self.k1 + r2 * (self.k2 + r2 * self.k3));
+            let dx = 2.0 * self.p1 * x * y + self.p2 * (r2 + 2.0 * x * x);
+            let dy = self.p1 * (r2 + 2.0 * y * y) + 2.0 * self.p2 * x * y;
+            x = (xd - dx) / radial;
+            y = (yd - dy) / radial;
+        }
+        (x, y)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Lens {
+    #[serde(default)]
+    pub distortion : Distortion,
+    // lateral chromatic aberration, red is magnified by (1 + this) and blue
+    // by (1 - this) relative to green
+    #[serde(default)]
+    pub chromatic_aberration : f32,
+    // 0.0 is off, 1.0 is the full cos^4 falloff of a real lens
+    #[serde(default)]
+    pub vignetting : f32,
+}
+
+impl Lens {
+    /// Whether rays leave the camera exactly as they would without a lens.
+    pub fn is_pinhole(&self) -> bool {
+        self.distortion.is_identity() && self.chromatic_aberration == 0.0
+    }
+
+    /// Maps a normalized image position to the ideal film position a ray is
+    /// traced through, for a channel magnified by `scale`.
+    pub fn film_position(&self, x : f32, y : f32, scale : f32) -> (f32, f32) {
+        let (x, y) = self.distortion.undistort(x, y);
+        (x / scale, y / scale)
+    }
+
+    /// Brightness falloff for a ray at angle `cos_theta` to the optical axis.
+    pub fn vignette(&self, cos_theta : f32) -> f32 {
+        let falloff = cos_theta.clamp(0.0, 1.0).powi(4);
+        1.0 + (falloff - 1.0) * self.vignetting
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undistort_inverts_distort() {
+        let distortion = Distortion { k1 : -0.2, k2 : 0.05, k3 : 0.0, p1 : 0.01, p2 : -0.005 };
+        let (xd, yd) = distortion.distort(0.6, -0.4);
+        let (x, y) = distortion.undistort(xd, yd);
+        assert!((x - 0.6).abs() < 1e-4);
+        assert!((y + 0.4).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_default_lens_is_pinhole() {
+        let lens = Lens::default();
+        assert!(lens.is_pinhole());
+        assert_eq!(lens.film_position(0.3, 0.2, 1.0), (0.3, 0.2));
+        assert_eq!(lens.vignette(0.5), 1.0);
+    }
+
+    #[test]
+    fn test_vignette() {
+        let lens = Lens { vignetting : 1.0, ..Lens::default() };
+        assert_eq!(lens.vignette(1.0), 1.0);
+        assert!((lens.vignette(0.5) - 0.0625).abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_chromatic_aberration_magnifies_channels() {
+        let lens = Lens { chromatic_aberration : 0.1, ..Lens::default() };
+        assert!(!lens.is_pinhole());
+        let (red, _) = lens.film_position(0.5, 0.0, 1.1);
+        let (blue, _) = lens.film_position(0.5, 0.0, 0.9);
+        assert!(red < 0.5 && blue > 0.5);
+    }
+}
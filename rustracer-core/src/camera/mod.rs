@@ -0,0 +1,2 @@
+pub mod lens;
+pub mod exposure;
\ No newline at end of file
@@ -0,0 +1,382 @@
+use crate::graphics::color::Color;
+use crate::graphics::light::Light;
+use crate::graphics::material::Material;
+use crate::math::sphere::Sphere;
+use crate::math::triangle::Triangle;
+use crate::math::vector::Vector;
+use crate::scene::{Problem, Scene, SceneError};
+
+// what a `f` corner refers to, as 0 based indices
+struct Corner {
+    v : usize,
+    vt : Option<usize>,
+    vn : Option<usize>,
+}
+
+// colour, (min, max) alpha and (near, far) distance, like `Scene`'s
+type DepthCue = (Color, (f32, f32), (f32, f32));
+
+// the state built up line by line
+#[derive(Default)]
+struct Importer {
+    eye : Option<Vector>,
+    view_dir : Option<Vector>,
+    up_dir : Option<Vector>,
+    hfov : Option<f32>,
+    resolution : Option<(i32, i32)>,
+    bkg_color : Color,
+    parallel : Option<f32>,
+    depth_cue : Option<DepthCue>,
+    materials : Vec<Material>,
+    // the material objects get, set by mtlcolor and texture
+    material : Option<usize>,
+    textures : Vec<String>,
+    spheres : Vec<Sphere>,
+    lights : Vec<Light>,
+    vertices : Vec<Vector>,
+    normals : Vec<Vector>,
+    uvs : Vec<[f32; 3]>,
+    triangles : Vec<Triangle>,
+}
+
+// lights are white, a coloured one only keeps its average brightness
+fn averaged(r : f32, g : f32, b : f32, i : f32) -> Option<String> {
+    (r != g || g != b).then(|| format!("light colour {} {} {} is averaged to a white light of intensity {}", r, g, b, i))
+}
+
+fn numbers<const N : usize>(args : &[&str], optional : usize) -> Result<[f32; N], String> {
+    if args.len() < N - optional || args.len() > N {
+        let expected = if optional == 0 { N.to_string() } else { format!("{} to {}", N - optional, N) };
+        return Err(format!("expected {} numbers, got {}", expected, args.len()));
+    }
+    let mut values = [0.0; N];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse::<f32>().map_err(|_| format!("invalid number: {}", arg))?;
+    }
+    Ok(values)
+}
+
+// a 1 based index into a list of `len`, negative ones count from the end
+fn index(arg : &str, len : usize) -> Result<usize, String> {
+    let i = arg.parse::<i64>().map_err(|_| format!("invalid index: {}", arg))?;
+    let resolved = if i < 0 { len as i64 + i } else { i - 1 };
+    if resolved < 0 || resolved >= len as i64 {
+        return Err(format!("index {} is out of range, {} defined so far", i, len));
+    }
+    Ok(resolved as usize)
+}
+
+impl Importer {
+    // Ok with a warning for lines that load but won't render as written
+    fn line(&mut self, keyword : &str, args : &[&str]) -> Result<Option<String>, String> {
+        let mut warning = None;
+        match keyword {
+            "eye" => {
+                let [x, y, z] = numbers(args, 0)?;
+                self.eye = Some(Vector::new(x, y, z, 1.0));
+            }
+            "viewdir" => {
+                let [x, y, z] = numbers(args, 0)?;
+                let mut v = Vector::new(x, y, z, 0.0);
+                v.normalize();
+                self.view_dir = Some(v);
+            }
+            "updir" => {
+                let [x, y, z] = numbers(args, 0)?;
+                self.up_dir = Some(Vector::new(x, y, z, 0.0));
+            }
+            "hfov" => self.hfov = Some(numbers::<1>(args, 0)?[0]),
+            "imsize" => {
+                let [w, h] = numbers(args, 0)?;
+                if w.fract() != 0.0 || h.fract() != 0.0 {
+                    return Err(format!("image size must be whole pixels, got {} x {}", w, h));
+                }
+                self.resolution = Some((w as i32, h as i32));
+            }
+            // an optional fourth value is the index of refraction of the
+            // surroundings, which the renderer doesn't use
+            "bkgcolor" => {
+                let [r, g, b, _] = numbers(args, 1)?;
+                self.bkg_color = Color::new(r, g, b);
+            }
+            "parallel" => self.parallel = Some(numbers::<1>(args, 0)?[0]),
+            "depthcueing" => {
+                let [r, g, b, alpha_max, alpha_min, dist_max, dist_min] = numbers(args, 0)?;
+                self.depth_cue = Some((Color::new(r, g, b), (alpha_min, alpha_max), (dist_min, dist_max)));
+            }
+            // diffuse, specular, ka kd ks, n, then optionally opacity and
+            // index of refraction
+            "mtlcolor" => {
+                let [dr, dg, db, sr, sg, sb, k_a, k_d, k_s, n, alpha, eta] = numbers(args, 2)?;
+                let alpha = if args.len() > 10 { alpha } else { 1.0 };
+                self.materials.push(Material::new(Color::new(dr, dg, db), Color::new(sr, sg, sb), k_a, k_d, k_s, alpha, eta, n as i32, None));
+                self.material = Some(self.materials.len() - 1);
+            }
+            // textures the current material from here on; the file is kept
+            // on the scene, but the renderer can't sample it yet
+            "texture" => {
+                let [filename] = args else {
+                    return Err("expected a texture file name".to_string());
+                };
+                let mut material = self.materials[self.current_material()?];
+                self.textures.push(filename.to_string());
+                material.texture = Some(self.textures.len() as i32 - 1);
+                self.materials.push(material);
+                self.material = Some(self.materials.len() - 1);
+                warning = Some(format!("texture {} isn't sampled yet, surfaces get the plain material colour", filename));
+            }
+            "sphere" => {
+                let [x, y, z, r] = numbers(args, 0)?;
+                let material = self.current_material()?;
+                self.spheres.push(Sphere::new(Vector::new(x, y, z, 1.0), r, material));
+            }
+            // w is 1 for point lights and 0 for directional ones; lights
+            // here are white, so a coloured one gets its average
+            "light" => {
+                if args.len() == 6 {
+                    return Err("expected 5 or 7 numbers, got 6".to_string());
+                }
+                let [x, y, z, w, r, g, b] = numbers(args, 2)?;
+                let i = if args.len() == 7 { (r + g + b) / 3.0 } else { r };
+                if args.len() == 7 {
+                    warning = averaged(r, g, b, i);
+                }
+                self.lights.push(Light::new(Vector::new(x, y, z, w), (1.0, 0.0, 0.0), i));
+            }
+            "attlight" => {
+                let ([x, y, z, w], rest) = match args.len() {
+                    8 | 10 => (numbers(&args[..4], 0)?, &args[4..]),
+                    n => return Err(format!("expected 8 or 10 numbers, got {}", n)),
+                };
+                let (i, c) = if rest.len() == 6 {
+                    let [r, g, b, c1, c2, c3] = numbers(rest, 0)?;
+                    let i = (r + g + b) / 3.0;
+                    warning = averaged(r, g, b, i);
+                    (i, (c1, c2, c3))
+                } else {
+                    let [i, c1, c2, c3] = numbers(rest, 0)?;
+                    (i, (c1, c2, c3))
+                };
+                self.lights.push(Light::new(Vector::new(x, y, z, w), c, i));
+            }
+            "v" => {
+                let [x, y, z] = numbers(args, 0)?;
+                self.vertices.push(Vector::new(x, y, z, 1.0));
+            }
+            "vn" => {
+                let [x, y, z] = numbers(args, 0)?;
+                self.normals.push(Vector::new(x, y, z, 0.0));
+            }
+            "vt" => {
+                let [u, v] = numbers(args, 0)?;
+                self.uvs.push([u, v, 0.0]);
+            }
+            "f" => self.face(args)?,
+            _ => return Err(format!("unknown keyword: {}", keyword)),
+        }
+        Ok(warning)
+    }
+
+    fn current_material(&self) -> Result<usize, String> {
+        self.material.ok_or("no mtlcolor has been given yet".to_string())
+    }
+
+    // `v`, `v/vt`, `v//vn` or `v/vt/vn`
+    fn corner(&self, arg : &str) -> Result<Corner, String> {
+        let mut parts = arg.split('/');
+        let v = index(parts.next().unwrap_or(""), self.vertices.len())?;
+        let vt = match parts.next() {
+            Some("") | None => None,
+            Some(vt) => Some(index(vt, self.uvs.len())?),
+        };
+        let vn = match parts.next() {
+            Some(vn) => Some(index(vn, self.normals.len())?),
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(format!("invalid face corner: {}", arg));
+        }
+        Ok(Corner { v, vt, vn })
+    }
+
+    fn face(&mut self, args : &[&str]) -> Result<(), String> {
+        let [a, b, c] = args else {
+            return Err(format!("faces must be triangles, got {} corners", args.len()));
+        };
+        let [a, b, c] = [self.corner(a)?, self.corner(b)?, self.corner(c)?];
+        let normals = match (a.vn, b.vn, c.vn) {
+            (Some(na), Some(nb), Some(nc)) => Some((self.normals[na], self.normals[nb], self.normals[nc])),
+            _ => None,
+        };
+        let uvs = match (a.vt, b.vt, c.vt) {
+            (Some(ta), Some(tb), Some(tc)) => Some((self.uvs[ta], self.uvs[tb], self.uvs[tc])),
+            _ => None,
+        };
+        self.triangles.push(Triangle {
+            position : (self.vertices[a.v], self.vertices[b.v], self.vertices[c.v]),
+            normals,
+            uvs,
+            material_index : self.current_material()?,
+        });
+        Ok(())
+    }
+
+    fn finish(self, problems : &mut Vec<Problem>) -> Option<Scene> {
+        let mut missing = |name : &str| problems.push(Problem { path : name.to_string(), message : "missing from the scene".to_string() });
+        let (Some(eye), Some(view_dir), Some(up_dir), Some(resolution)) = (self.eye, self.view_dir, self.up_dir, self.resolution) else {
+            for (name, given) in [("eye", self.eye.is_some()), ("viewdir", self.view_dir.is_some()), ("updir", self.up_dir.is_some()), ("imsize", self.resolution.is_some())] {
+                if !given {
+                    missing(name);
+                }
+            }
+            return None;
+        };
+        let hfov = match (self.hfov, self.parallel) {
+            (Some(hfov), _) => hfov,
+            // parallel projections don't need one
+            (None, Some(_)) => 90.0,
+            (None, None) => {
+                missing("hfov");
+                return None;
+            }
+        };
+        let (dc, alpha, dist) = self.depth_cue.unwrap_or((Color::default(), (1.0, 1.0), (0.0, 0.0)));
+        let mut scene = Scene::new(
+            self.materials,
+            self.spheres,
+            self.lights,
+            self.triangles,
+            eye,
+            view_dir,
+            up_dir,
+            hfov,
+            resolution,
+            alpha,
+            dist,
+            self.bkg_color,
+            self.parallel.unwrap_or(0.0),
+            dc,
+            self.parallel.is_some(),
+            String::new()
+        );
+        scene.textures = self.textures;
+        Some(scene)
+    }
+}
+
+/// Reads a scene in the line based text format of the C++ raytracer this
+/// one was ported from, one keyword and its arguments per line with `#`
+/// comments. Every bad line is reported by its line number. Lines the
+/// renderer can't reproduce, textures and coloured lights, are printed as
+/// warnings on stderr.
+pub fn parse(text : &str) -> Result<Scene, SceneError> {
+    let (scene, warnings) = parse_with_warnings(text)?;
+    for warning in warnings {
+        eprintln!("Warning: {}: {}", warning.path, warning.message);
+    }
+    Ok(scene)
+}
+
+/// `parse`, returning the warnings instead of printing them.
+pub fn parse_with_warnings(text : &str) -> Result<(Scene, Vec<Problem>), SceneError> {
+    let mut importer = Importer::default();
+    let mut problems = Vec::new();
+    let mut warnings = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args : Vec<&str> = words.collect();
+        match importer.line(keyword, &args) {
+            Ok(None) => {}
+            Ok(Some(message)) => warnings.push(Problem { path : format!("line {}", n + 1), message }),
+            Err(message) => problems.push(Problem { path : format!("line {}", n + 1), message }),
+        }
+    }
+    match importer.finish(&mut problems) {
+        Some(scene) if problems.is_empty() => Ok((scene, warnings)),
+        _ => Err(SceneError::Invalid(problems)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE : &str = "
+        # two spheres and a textured triangle
+        eye 0 0 5
+        viewdir 0 0 -2
+        updir 0 1 0
+        hfov 60
+        imsize 64 48
+        bkgcolor 0.1 0.1 0.1 1.0
+        depthcueing 0.5 0.5 0.5 1.0 0.2 20 2
+        light 0 5 0 1 1 0.5 0
+        attlight 0 0 5 1 2 1 0.5 0
+
+        mtlcolor 1 0 0 1 1 1 0.2 0.6 0.2 10
+        sphere 0 0 -3 1
+        mtlcolor 0 0 1 1 1 1 0.2 0.6 0.2 20 0.5 1.5
+        sphere 2 0 -4 0.5
+
+        texture earth.ppm
+        v -1 -1 -2
+        v 1 -1 -2
+        v 0 1 -2
+        vt 0 0
+        vt 1 0
+        vt 0.5 1
+        vn 0 0 1
+        f 1/1/1 2/2/1 3/3/1
+        f -3//1 -2//1 -1//1
+    ";
+
+    #[test]
+    fn test_parse() {
+        let (scene, warnings) = parse_with_warnings(SCENE).unwrap();
+        assert_eq!(scene.resolution, (64, 48));
+        assert_eq!(scene.view_dir, Vector::new(0.0, 0.0, -1.0, 0.0));
+        assert_eq!(scene.dist, (2.0, 20.0));
+        assert_eq!(scene.alpha, (0.2, 1.0));
+        assert_eq!(scene.lights[1].attenuation, (1.0, 0.5, 0.0));
+        assert_eq!(scene.spheres[1].material_index, 1);
+        assert_eq!(scene.materials[1].alpha, 0.5);
+        // texturing makes a textured copy of the current material
+        assert_eq!(scene.materials.len(), 3);
+        assert_eq!(scene.materials[2].texture, Some(0));
+        assert_eq!(scene.textures, vec!["earth.ppm".to_string()]);
+        assert_eq!(scene.triangles.len(), 2);
+        assert_eq!(scene.triangles[0].uvs.unwrap().2, [0.5, 1.0, 0.0]);
+        assert!(scene.triangles[1].uvs.is_none());
+        assert_eq!(scene.triangles[1].material_index, 2);
+        scene.validate().unwrap();
+        // the coloured light and the texture
+        assert_eq!(scene.lights[0].i, 0.5);
+        let lines : Vec<&str> = warnings.iter().map(|w| w.path.as_str()).collect();
+        assert_eq!(lines, vec!["line 10", "line 18"]);
+        assert!(warnings[0].message.contains("intensity 0.5"));
+    }
+
+    #[test]
+    fn test_triangles_are_not_written_away() {
+        let scene = parse(SCENE).unwrap();
+        let filename = std::env::temp_dir().join(format!("rustracer_classic_{}.json", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        assert!(matches!(scene.write_to_file(filename), Err(SceneError::Encoding(_))));
+        assert!(std::fs::metadata(filename).is_err());
+    }
+
+    #[test]
+    fn test_problems_are_reported_by_line() {
+        let problems = match parse("eye 0 0\nsphere 0 0 0 1\nf 1 2 3\nfog 1\ntexture\n") {
+            Err(SceneError::Invalid(problems)) => problems,
+            other => panic!("expected problems, got {:?}", other.map(|_| ())),
+        };
+        let paths : Vec<&str> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec!["line 1", "line 2", "line 3", "line 4", "line 5", "eye", "viewdir", "updir", "imsize"]);
+        assert_eq!(problems[3].message, "unknown keyword: fog");
+    }
+}
@@ -0,0 +1,120 @@
+// Image similarity metrics for checking renders against reference images.
+// All functions take interleaved 8 bit RGB.
+
+// constants from Wang et al. 2004 for 8 bit values
+const C1 : f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const C2 : f64 = (0.03 * 255.0) * (0.03 * 255.0);
+const WINDOW : usize = 8;
+
+/// Peak signal-to-noise ratio in dB, infinite for identical images.
+pub fn psnr(a : &[u8], b : &[u8]) -> f64 {
+    assert_eq!(a.len(), b.len(), "images differ in size");
+    if a.is_empty() {
+        return f64::INFINITY;
+    }
+    let mse = a.iter()
+        .zip(b)
+        .map(|(x, y)| (*x as f64 - *y as f64).powi(2))
+        .sum::<f64>() / a.len() as f64;
+    if mse == 0.0 {
+        f64::INFINITY
+    } else {
+        10.0 * (255.0 * 255.0 / mse).log10()
+    }
+}
+
+fn luma(rgb : &[u8]) -> Vec<f64> {
+    rgb.chunks(3)
+        .map(|c| 0.299 * c[0] as f64 + 0.587 * c[1] as f64 + 0.114 * c[2] as f64)
+        .collect()
+}
+
+/// Mean structural similarity of the luma of two images, 1.0 for identical
+/// images. Uses 8x8 windows overlapping by half; images smaller than a
+/// window are compared as a single window.
+pub fn ssim(a : &[u8], b : &[u8], width : usize, height : usize) -> f64 {
+    assert_eq!(a.len(), b.len(), "images differ in size");
+    assert_eq!(a.len(), width * height * 3, "image is not {}x{}", width, height);
+    let (a, b) = (luma(a), luma(b));
+    let (w, h) = (WINDOW.min(width), WINDOW.min(height));
+    if w == 0 || h == 0 {
+        return 1.0;
+    }
+
+    let mut total = 0.0;
+    let mut windows = 0;
+    let mut y = 0;
+    loop {
+        let mut x = 0;
+        loop {
+            total += window_ssim(&a, &b, width, x, y, w, h);
+            windows += 1;
+            if x + w >= width {
+                break;
+            }
+            x = (x + w / 2).min(width - w);
+        }
+        if y + h >= height {
+            break;
+        }
+        y = (y + h / 2).min(height - h);
+    }
+    total / windows as f64
+}
+
+fn window_ssim(a : &[f64], b : &[f64], stride : usize, x0 : usize, y0 : usize, w : usize, h : usize) -> f64 {
+    let n = (w * h) as f64;
+    let pixels = || (y0..y0 + h).flat_map(move |y| (x0..x0 + w).map(move |x| y * stride + x));
+    let mean_a = pixels().map(|i| a[i]).sum::<f64>() / n;
+    let mean_b = pixels().map(|i| b[i]).sum::<f64>() / n;
+    let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+    for i in pixels() {
+        let (da, db) = (a[i] - mean_a, b[i] - mean_b);
+        var_a += da * da;
+        var_b += db * db;
+        cov += da * db;
+    }
+    let (var_a, var_b, cov) = (var_a / n, var_b / n, cov / n);
+    ((2.0 * mean_a * mean_b + C1) * (2.0 * cov + C2))
+        / ((mean_a * mean_a + mean_b * mean_b + C1) * (var_a + var_b + C2))
+}
+
+/// The absolute difference of two images, amplified so small errors show.
+pub fn diff_image(a : &[u8], b : &[u8]) -> Vec<u8> {
+    assert_eq!(a.len(), b.len(), "images differ in size");
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x.abs_diff(*y) as u32 * 8).min(255) as u8)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient(width : usize, height : usize) -> Vec<u8> {
+        (0..width * height).flat_map(|i| {
+            let v = ((i % width) * 255 / width) as u8;
+            [v, v / 2, 255 - v]
+        }).collect()
+    }
+
+    #[test]
+    fn test_identical_images() {
+        let image = gradient(20, 12);
+        assert_eq!(psnr(&image, &image), f64::INFINITY);
+        assert!((ssim(&image, &image, 20, 12) - 1.0).abs() < 1e-9);
+        assert!(diff_image(&image, &image).iter().all(|v| *v == 0));
+    }
+
+    #[test]
+    fn test_metrics_drop_with_noise() {
+        let image = gradient(20, 12);
+        let slightly = image.iter().enumerate().map(|(i, v)| v.saturating_add((i % 3) as u8)).collect::<Vec<u8>>();
+        let very = image.iter().enumerate().map(|(i, v)| v.wrapping_add((i * 37 % 101) as u8)).collect::<Vec<u8>>();
+        assert!(psnr(&image, &slightly) > 40.0);
+        assert!(psnr(&image, &very) < psnr(&image, &slightly));
+        assert!(ssim(&image, &slightly, 20, 12) > 0.95);
+        assert!(ssim(&image, &very, 20, 12) < ssim(&image, &slightly, 20, 12));
+    }
+}
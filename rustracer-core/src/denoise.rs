@@ -0,0 +1,287 @@
+use core::fmt;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::aov::{Aov, AovBuffer};
+use crate::graphics::color::Color;
+
+// B3 spline, the 5 tap filter of the a-trous wavelet transform
+const KERNEL : [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// The feature buffers that tell the denoiser where the edges are. Each is
+/// row-major with one entry per pixel, as rendered by `Raytracer::trace_aovs`.
+pub struct Guides {
+    pub albedo : Vec<Color>,
+    pub normal : Vec<Color>,
+    // depth in the red channel, infinite for the background
+    pub depth : Vec<Color>,
+}
+
+impl Guides {
+    /// The passes to render for `from_aovs`.
+    pub const AOVS : [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+    /// Picks the guides out of rendered passes, `None` if one is missing.
+    pub fn from_aovs(buffers : Vec<AovBuffer>) -> Option<Self> {
+        let mut albedo = None;
+        let mut normal = None;
+        let mut depth = None;
+        for buffer in buffers {
+            match buffer.aov {
+                Aov::Albedo => albedo = Some(buffer.pixels),
+                Aov::Normal => normal = Some(buffer.pixels),
+                Aov::Depth => depth = Some(buffer.pixels),
+                _ => {}
+            }
+        }
+        Some(Guides {
+            albedo : albedo?,
+            normal : normal?,
+            depth : depth?,
+        })
+    }
+}
+
+/// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010). Every
+/// iteration is a 5x5 cross bilateral blur with its taps spread twice as far
+/// apart as the last, weighted down across changes in colour, normal and
+/// depth. Texture detail is kept by filtering the image divided by albedo.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Denoiser {
+    #[serde(default = "default_iterations")]
+    pub iterations : u32,
+    // how far apart colours can be and still be blended, halved every pass
+    #[serde(default = "default_color_sigma")]
+    pub color_sigma : f32,
+    // exponent on the cosine between normals
+    #[serde(default = "default_normal_power")]
+    pub normal_power : f32,
+    // allowed depth difference relative to the depth and the tap distance
+    #[serde(default = "default_depth_sigma")]
+    pub depth_sigma : f32,
+}
+
+fn default_iterations() -> u32 {
+    5
+}
+
+fn default_color_sigma() -> f32 {
+    0.5
+}
+
+fn default_normal_power() -> f32 {
+    64.0
+}
+
+fn default_depth_sigma() -> f32 {
+    0.05
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations : default_iterations(),
+            color_sigma : default_color_sigma(),
+            normal_power : default_normal_power(),
+            depth_sigma : default_depth_sigma(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DenoiseError {
+    // a buffer, by name, does not hold one entry per pixel
+    Size { buffer : &'static str, expected : usize, actual : usize },
+}
+
+impl fmt::Display for DenoiseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DenoiseError::Size { buffer, expected, actual } => write!(f, "denoiser {} buffer holds {} pixels, expected {}", buffer, actual, expected),
+        }
+    }
+}
+
+impl std::error::Error for DenoiseError {}
+
+impl Denoiser {
+    /// Denoises a row-major `width` x `height` image. Pixels that are not
+    /// finite are left out of the blur and filled in from their neighbours.
+    pub fn apply(&self, pixels : &[Color], width : i32, height : i32, guides : &Guides) -> Result<Vec<Color>, DenoiseError> {
+        let n = width.max(0) as usize * height.max(0) as usize;
+        for (buffer, len) in [("image", pixels.len()), ("albedo", guides.albedo.len()), ("normal", guides.normal.len()), ("depth", guides.depth.len())] {
+            if len != n {
+                return Err(DenoiseError::Size { buffer, expected : n, actual : len });
+            }
+        }
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+
+        let mut illumination : Vec<Color> = pixels.iter()
+            .zip(&guides.albedo)
+            .map(|(c, a)| Color::new(demodulate(c.r, a.r), demodulate(c.g, a.g), demodulate(c.b, a.b)))
+            .collect();
+        for i in 0..self.iterations {
+            illumination = self.pass(&illumination, width, height, guides, 1 << i, i);
+        }
+
+        Ok(illumination.iter()
+            .zip(&guides.albedo)
+            .map(|(c, a)| Color::new(remodulate(c.r, a.r), remodulate(c.g, a.g), remodulate(c.b, a.b)))
+            .collect())
+    }
+
+    fn pass(&self, input : &[Color], width : i32, height : i32, guides : &Guides, step : i32, iteration : u32) -> Vec<Color> {
+        let sigma = self.color_sigma * 0.5f32.powi(iteration as i32);
+        let color_falloff = 1.0 / (sigma * sigma).max(1e-8);
+        let mut output = vec![Color::new(0.0, 0.0, 0.0); input.len()];
+        output.par_chunks_mut(width as usize).enumerate().for_each(|(y, row)| {
+            let y = y as i32;
+            for (x, out) in row.iter_mut().enumerate() {
+                let x = x as i32;
+                let p = (y * width + x) as usize;
+                let (c_p, n_p, z_p) = (input[p], guides.normal[p], guides.depth[p].r);
+                let p_finite = is_finite(c_p);
+
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+                for (j, kj) in KERNEL.iter().enumerate() {
+                    let qy = y + (j as i32 - 2) * step;
+                    if qy < 0 || qy >= height {
+                        continue;
+                    }
+                    for (i, ki) in KERNEL.iter().enumerate() {
+                        let qx = x + (i as i32 - 2) * step;
+                        if qx < 0 || qx >= width {
+                            continue;
+                        }
+                        let q = (qy * width + qx) as usize;
+                        let c_q = input[q];
+                        if !is_finite(c_q) {
+                            continue;
+                        }
+                        // a non-finite pixel takes its neighbours' colour whatever it is
+                        let w_color = if p_finite {
+                            let (dr, dg, db) = (c_p.r - c_q.r, c_p.g - c_q.g, c_p.b - c_q.b);
+                            (-(dr * dr + dg * dg + db * db) * color_falloff).exp()
+                        } else {
+                            1.0
+                        };
+                        let w = ki * kj
+                            * w_color
+                            * self.normal_weight(n_p, guides.normal[q])
+                            * self.depth_weight(z_p, guides.depth[q].r, step);
+                        sum = sum + c_q * w;
+                        total += w;
+                    }
+                }
+                // only zero when the centre and every tap around it are non-finite
+                *out = if total > 0.0 { sum * (1.0 / total) } else { Color::new(0.0, 0.0, 0.0) };
+            }
+        });
+        output
+    }
+
+    fn normal_weight(&self, a : Color, b : Color) -> f32 {
+        let dot = a.r * b.r + a.g * b.g + a.b * b.b;
+        let a_missed = a.r == 0.0 && a.g == 0.0 && a.b == 0.0;
+        let b_missed = b.r == 0.0 && b.g == 0.0 && b.b == 0.0;
+        if a_missed || b_missed {
+            return if a_missed == b_missed { 1.0 } else { 0.0 };
+        }
+        dot.max(0.0).powf(self.normal_power)
+    }
+
+    fn depth_weight(&self, a : f32, b : f32, step : i32) -> f32 {
+        if !a.is_finite() || !b.is_finite() {
+            return if a.is_finite() == b.is_finite() { 1.0 } else { 0.0 };
+        }
+        let scale = self.depth_sigma * a.abs().max(1e-3) * step as f32;
+        (-(a - b).abs() / scale).exp()
+    }
+}
+
+fn is_finite(c : Color) -> bool {
+    c.r.is_finite() && c.g.is_finite() && c.b.is_finite()
+}
+
+// black albedo carries no texture information, filter the colour itself
+fn demodulate(c : f32, albedo : f32) -> f32 {
+    if albedo > 1e-3 { c / albedo } else { c }
+}
+
+fn remodulate(c : f32, albedo : f32) -> f32 {
+    if albedo > 1e-3 { c * albedo } else { c }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a 16x16 image split down the middle, facing +z on the left and +x on
+    // the right
+    fn guides() -> Guides {
+        let normal = (0..256).map(|i| if i % 16 < 8 { Color::new(0.0, 0.0, 1.0) } else { Color::new(1.0, 0.0, 0.0) }).collect();
+        Guides {
+            albedo : vec![Color::new(1.0, 1.0, 1.0); 256],
+            normal,
+            depth : vec![Color::new(5.0, 5.0, 5.0); 256],
+        }
+    }
+
+    fn noise(i : usize) -> f32 {
+        ((i as f32 * 12.9898).sin() * 43758.547).rem_euclid(1.0) * 0.2 - 0.1
+    }
+
+    #[test]
+    fn test_denoise_flattens_noise() {
+        let pixels : Vec<Color> = (0..256).map(|i| {
+            let v = 0.5 + noise(i);
+            Color::new(v, v, v)
+        }).collect();
+        let guides = Guides { normal : vec![Color::new(0.0, 0.0, 1.0); 256], ..guides() };
+        let denoised = Denoiser::default().apply(&pixels, 16, 16, &guides).unwrap();
+        let deviation = |p : &[Color]| p.iter().map(|c| (c.r - 0.5).abs()).fold(0.0, f32::max);
+        assert!(deviation(&denoised) < deviation(&pixels) * 0.5);
+    }
+
+    #[test]
+    fn test_denoise_keeps_edges() {
+        let pixels : Vec<Color> = (0..256).map(|i| {
+            let v = if i % 16 < 8 { 0.2 } else { 0.8 } + noise(i);
+            Color::new(v, v, v)
+        }).collect();
+        let denoised = Denoiser::default().apply(&pixels, 16, 16, &guides()).unwrap();
+        // either side of the edge
+        assert!((denoised[7 * 16 + 7].r - 0.2).abs() < 0.08);
+        assert!((denoised[7 * 16 + 8].r - 0.8).abs() < 0.08);
+    }
+
+    #[test]
+    fn test_non_finite_pixels_are_filled_in() {
+        let mut pixels = vec![Color::new(0.5, 0.5, 0.5); 256];
+        pixels[17] = Color::new(f32::NAN, 0.5, 0.5);
+        pixels[40] = Color::new(f32::INFINITY, 0.5, 0.5);
+        let guides = Guides { normal : vec![Color::new(0.0, 0.0, 1.0); 256], ..guides() };
+        let denoised = Denoiser::default().apply(&pixels, 16, 16, &guides).unwrap();
+        assert!(denoised.iter().all(|c| (c.r - 0.5).abs() < 1e-4));
+    }
+
+    #[test]
+    fn test_bad_sizes_are_errors() {
+        let denoiser = Denoiser::default();
+        assert_eq!(denoiser.apply(&[], 0, 0, &Guides { albedo : Vec::new(), normal : Vec::new(), depth : Vec::new() }), Ok(Vec::new()));
+        let pixels = vec![Color::new(0.5, 0.5, 0.5); 256];
+        let guides = Guides { depth : Vec::new(), ..guides() };
+        assert_eq!(denoiser.apply(&pixels, 16, 16, &guides), Err(DenoiseError::Size { buffer : "depth", expected : 256, actual : 0 }));
+    }
+
+    #[test]
+    fn test_guides_need_all_passes() {
+        let buffer = |aov| AovBuffer { aov, pixels : Vec::new() };
+        assert!(Guides::from_aovs(vec![buffer(Aov::Albedo), buffer(Aov::Normal)]).is_none());
+        assert!(Guides::from_aovs(vec![buffer(Aov::Depth), buffer(Aov::Albedo), buffer(Aov::Normal)]).is_some());
+    }
+}
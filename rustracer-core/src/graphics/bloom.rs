@@ -0,0 +1,142 @@
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::color::Color;
+use crate::graphics::image::{BorderMode, Image, ResizeFilter};
+
+/// Glow around bright parts of the image, the light a real lens scatters.
+/// Everything brighter than `threshold` is blurred at `levels` scales, each
+/// twice as wide as the last, and added back scaled by `intensity`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Bloom {
+    // luminance above which pixels start to glow
+    #[serde(default = "default_threshold")]
+    pub threshold : f32,
+    #[serde(default = "default_intensity")]
+    pub intensity : f32,
+    #[serde(default = "default_levels")]
+    pub levels : u32,
+}
+
+fn default_threshold() -> f32 {
+    1.0
+}
+
+fn default_intensity() -> f32 {
+    0.2
+}
+
+fn default_levels() -> u32 {
+    5
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Bloom {
+            threshold : default_threshold(),
+            intensity : default_intensity(),
+            levels : default_levels(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BloomError {
+    // the image doesn't hold width x height pixels
+    Size { width : i32, height : i32, actual : usize },
+}
+
+impl fmt::Display for BloomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BloomError::Size { width, height, actual } => write!(f, "bloom image has {} pixels but should be {}x{}", actual, width, height),
+        }
+    }
+}
+
+impl std::error::Error for BloomError {}
+
+impl Bloom {
+    /// Adds the glow to a row-major `width` x `height` image of exposed
+    /// radiance.
+    pub fn apply(&self, pixels : &mut [Color], width : i32, height : i32) -> Result<(), BloomError> {
+        if pixels.len() != width.max(0) as usize * height.max(0) as usize {
+            return Err(BloomError::Size { width, height, actual : pixels.len() });
+        }
+        if self.levels == 0 || self.intensity == 0.0 || pixels.is_empty() {
+            return Ok(());
+        }
+
+        // the part of each pixel above the threshold, keeping its hue
+        let bright = Image::from_pixels(width as usize, height as usize, pixels.to_vec()).map(|c| {
+            let l = c.luminance();
+            if l.is_finite() && l > self.threshold {
+                c * ((l - self.threshold) / l)
+            } else {
+                Color::new(0.0, 0.0, 0.0)
+            }
+        });
+
+        let mut pyramid = vec![bright];
+        while pyramid.len() < self.levels as usize {
+            let last = pyramid.last().unwrap();
+            if last.width == 1 && last.height == 1 {
+                break;
+            }
+            pyramid.push(last.resize(last.width.div_ceil(2), last.height.div_ceil(2), ResizeFilter::Bilinear));
+        }
+
+        // blur every level and sum them from the coarsest up
+        let count = pyramid.len() as f32;
+        let mut glow : Option<Image> = None;
+        for level in pyramid.iter().rev() {
+            let mut blurred = level.gaussian_blur(1.0, BorderMode::Clamp);
+            if let Some(coarser) = glow {
+                let upsampled = coarser.resize(level.width, level.height, ResizeFilter::Bilinear);
+                for (c, u) in blurred.pixels.iter_mut().zip(upsampled.pixels) {
+                    *c = *c + u;
+                }
+            }
+            glow = Some(blurred);
+        }
+
+        let scale = self.intensity / count;
+        for (c, g) in pixels.iter_mut().zip(glow.unwrap().pixels) {
+            *c = *c + g * scale;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_spreads_bright_pixels() {
+        let mut pixels = vec![Color::new(0.5, 0.5, 0.5); 32 * 32];
+        pixels[16 * 32 + 16] = Color::new(50.0, 50.0, 50.0);
+        Bloom::default().apply(&mut pixels, 32, 32).unwrap();
+        // neighbours and pixels further out pick up some glow, less with distance
+        let near = pixels[16 * 32 + 18].r;
+        let far = pixels[16 * 32 + 26].r;
+        assert!(near > far && far > 0.5);
+    }
+
+    #[test]
+    fn test_bloom_ignores_dim_images() {
+        let mut pixels = vec![Color::new(0.8, 0.2, 0.4); 10 * 7];
+        let original = pixels.clone();
+        Bloom::default().apply(&mut pixels, 10, 7).unwrap();
+        assert!(pixels == original);
+    }
+
+    #[test]
+    fn test_bad_sizes_are_errors() {
+        let mut pixels = vec![Color::new(0.5, 0.5, 0.5); 12];
+        assert_eq!(Bloom::default().apply(&mut pixels, 4, 4), Err(BloomError::Size { width : 4, height : 4, actual : 12 }));
+        // would overflow an i32 product
+        assert!(Bloom::default().apply(&mut pixels, 100_000, 100_000).is_err());
+    }
+}
@@ -3,7 +3,7 @@ use::std::ops::{Add, Mul};
 use std::cmp::PartialEq;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Copy, Clone, Deserialize, Serialize)]
+#[derive(Debug, Copy, Clone, Default, Deserialize, Serialize)]
 pub struct Color {
     pub r: f32,
     pub g: f32,
@@ -12,26 +12,27 @@ pub struct Color {
 
 impl Color {
     pub fn new(r: f32, g: f32, b: f32) -> Self {
-        Color { r: r, g: g, b: b }
+        Color { r, g, b }
     }
 
+    /// Limits each channel to 0.0 to 1.0.
     pub fn clamp(&mut self) {
-        if self.r > 1.0 {
-            self.r = 1.0;
-        }
-        if self.g > 1.0 {
-            self.g = 1.0;
-        }
-        if self.b > 1.0 {
-            self.b = 1.0;
-        }
+        self.r = self.r.clamp(0.0, 1.0);
+        self.g = self.g.clamp(0.0, 1.0);
+        self.b = self.b.clamp(0.0, 1.0);
     }
 
+    /// Quantizes the channels as they are, with no tone mapping or gamma. Use
+    /// `tonemap::DisplayTransform` to turn rendered radiance into an image.
     pub fn to_u8(&self) -> (u8, u8, u8) {
-        let r = (self.r * 255.0) as u8;
-        let g = (self.g * 255.0) as u8;
-        let b = (self.b * 255.0) as u8;
-        (r, g, b)
+        let mut c = *self;
+        c.clamp();
+        ((c.r * 255.0) as u8, (c.g * 255.0) as u8, (c.b * 255.0) as u8)
+    }
+
+    /// Relative luminance of a linear Rec. 709 colour.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
     }
 
     pub fn normalize(&self) -> Self {
@@ -69,12 +70,6 @@ impl PartialEq for Color {
         f32::abs(self.g - other.g) < f32::EPSILON &&
         f32::abs(self.b - other.b) < f32::EPSILON
     }
-
-    fn ne(&self, other: &Color) -> bool {
-        f32::abs(self.r - other.r) > f32::EPSILON ||
-        f32::abs(self.g - other.g) > f32::EPSILON ||
-        f32::abs(self.b - other.b) > f32::EPSILON
-    }
 }
 
 impl fmt::Display for Color {
@@ -112,6 +107,13 @@ fn test_color_clamp() {
     assert_eq!(color, Color::new(1.0, 0.5, 1.0));
 }
 
+#[test]
+fn test_color_clamp_negative() {
+    let mut color = Color::new(-0.5, 0.5, -2.0);
+    color.clamp();
+    assert_eq!(color, Color::new(0.0, 0.5, 0.0));
+}
+
 #[test]
 fn test_color_to_u8() {
     let color = Color::new(0.5, 0.5, 0.5);
@@ -119,6 +121,12 @@ fn test_color_to_u8() {
     assert_eq!((r, g, b), (127, 127, 127));
 }
 
+#[test]
+fn test_color_luminance() {
+    assert!((Color::new(1.0, 1.0, 1.0).luminance() - 1.0).abs() < f32::EPSILON);
+    assert!(Color::new(0.0, 1.0, 0.0).luminance() > Color::new(1.0, 0.0, 0.0).luminance());
+}
+
 #[test]
 fn test_color_partial_eq() {
     let color1 = Color::new(0.5, 0.5, 0.5);
@@ -0,0 +1,182 @@
+use core::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::color::Color;
+use crate::graphics::tonemap::{srgb_decode, srgb_encode};
+
+type Matrix = [[f32; 3]; 3];
+
+const REC709_TO_XYZ : Matrix = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.072175],
+    [0.0193339, 0.119192, 0.9503041],
+];
+
+const XYZ_TO_REC709 : Matrix = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.969266, 1.8760108, 0.041556],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+// with a Bradford adaptation between the D65 and ACES white points
+const REC709_TO_ACESCG : Matrix = [
+    [0.6130974, 0.3395231, 0.0473795],
+    [0.0701937, 0.9163539, 0.0134524],
+    [0.0206156, 0.1095698, 0.8698146],
+];
+
+const ACESCG_TO_REC709 : Matrix = [
+    [1.705051, -0.6217921, -0.0832589],
+    [-0.1302564, 1.1408047, -0.0105483],
+    [-0.0240034, -0.128969, 1.1529723],
+];
+
+fn mul(m : &Matrix, c : Color) -> Color {
+    Color::new(
+        m[0][0] * c.r + m[0][1] * c.g + m[0][2] * c.b,
+        m[1][0] * c.r + m[1][1] * c.g + m[1][2] * c.b,
+        m[2][0] * c.r + m[2][1] * c.g + m[2][2] * c.b,
+    )
+}
+
+/// The colour spaces colours can be written in. The renderer works in
+/// linear Rec.709, which shares its primaries and white point with sRGB.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    // sRGB transfer curve on Rec.709 primaries, what colour pickers and 8 bit
+    // images use
+    Srgb,
+    #[default]
+    LinearRec709,
+    // linear AP1 primaries, the ACES working space
+    #[serde(rename = "acescg")]
+    AcesCg,
+    // CIE 1931 XYZ, D65 white
+    Xyz,
+}
+
+impl ColorSpace {
+    /// Linear Rec.709, the space the renderer works in.
+    pub fn to_working(&self, color : Color) -> Color {
+        match self {
+            ColorSpace::Srgb => Color::new(srgb_decode(color.r), srgb_decode(color.g), srgb_decode(color.b)),
+            ColorSpace::LinearRec709 => color,
+            ColorSpace::AcesCg => mul(&ACESCG_TO_REC709, color),
+            ColorSpace::Xyz => mul(&XYZ_TO_REC709, color),
+        }
+    }
+
+    pub fn from_working(&self, color : Color) -> Color {
+        match self {
+            ColorSpace::Srgb => Color::new(srgb_encode(color.r), srgb_encode(color.g), srgb_encode(color.b)),
+            ColorSpace::LinearRec709 => color,
+            ColorSpace::AcesCg => mul(&REC709_TO_ACESCG, color),
+            ColorSpace::Xyz => mul(&REC709_TO_XYZ, color),
+        }
+    }
+
+    pub fn convert(color : Color, from : ColorSpace, to : ColorSpace) -> Color {
+        if from == to {
+            return color;
+        }
+        to.from_working(from.to_working(color))
+    }
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "srgb" => Ok(ColorSpace::Srgb),
+            "linear" | "linear_rec709" => Ok(ColorSpace::LinearRec709),
+            "acescg" => Ok(ColorSpace::AcesCg),
+            "xyz" => Ok(ColorSpace::Xyz),
+            _ => Err(format!("unknown colour space: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ColorSpace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColorSpace::Srgb => "srgb",
+            ColorSpace::LinearRec709 => "linear_rec709",
+            ColorSpace::AcesCg => "acescg",
+            ColorSpace::Xyz => "xyz",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// How the colours in a scene file are written: values are divided by
+/// `scale` (255.0 for 8 bit colour pickers) and then read in `space`.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct ColorEncoding {
+    #[serde(default)]
+    pub space : ColorSpace,
+    #[serde(default = "default_scale")]
+    pub scale : f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl Default for ColorEncoding {
+    fn default() -> Self {
+        ColorEncoding {
+            space : ColorSpace::default(),
+            scale : default_scale(),
+        }
+    }
+}
+
+impl ColorEncoding {
+    pub fn is_working(&self) -> bool {
+        *self == ColorEncoding::default()
+    }
+
+    pub fn decode(&self, color : Color) -> Color {
+        self.space.to_working(color * (1.0 / self.scale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a : Color, b : Color) -> bool {
+        (a.r - b.r).abs() < 1e-4 && (a.g - b.g).abs() < 1e-4 && (a.b - b.b).abs() < 1e-4
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let color = Color::new(0.8, 0.3, 0.05);
+        for space in [ColorSpace::Srgb, ColorSpace::LinearRec709, ColorSpace::AcesCg, ColorSpace::Xyz] {
+            assert!(close(space.to_working(space.from_working(color)), color), "{}", space);
+        }
+        let aces = ColorSpace::convert(color, ColorSpace::LinearRec709, ColorSpace::AcesCg);
+        assert!(close(ColorSpace::convert(aces, ColorSpace::AcesCg, ColorSpace::Xyz), ColorSpace::convert(color, ColorSpace::LinearRec709, ColorSpace::Xyz)));
+    }
+
+    #[test]
+    fn test_white_stays_white() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        assert!(close(ColorSpace::AcesCg.to_working(white), white));
+        let xyz = ColorSpace::Xyz.from_working(white);
+        // D65 white point
+        assert!((xyz.r - 0.9505).abs() < 1e-3 && (xyz.g - 1.0).abs() < 1e-3 && (xyz.b - 1.089).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_encoding_decode() {
+        let encoding : ColorEncoding = serde_json::from_str(r#"{"space": "srgb", "scale": 255}"#).unwrap();
+        let decoded = encoding.decode(Color::new(255.0, 0.0, 188.0));
+        assert!(close(decoded, Color::new(1.0, 0.0, 0.5029)));
+        assert!(ColorEncoding::default().is_working());
+    }
+}
@@ -0,0 +1,431 @@
+use core::fmt;
+
+use rayon::prelude::*;
+
+use crate::graphics::color::Color;
+use crate::graphics::texture::Texture;
+
+/// What a filter sees past the edge of the image.
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum BorderMode {
+    Constant(Color),
+    // repeat the edge pixel
+    #[default]
+    Clamp,
+    // tile the image
+    Wrap,
+    // reflect about the edge without repeating it: 2 1 | 0 1 2 | 1 0
+    Mirror,
+}
+
+impl BorderMode {
+    // the in-range index to read for `i`, or None for a constant border
+    fn index(&self, i : isize, size : usize) -> Option<usize> {
+        let n = size as isize;
+        if (0..n).contains(&i) {
+            return Some(i as usize);
+        }
+        match self {
+            BorderMode::Constant(_) => None,
+            BorderMode::Clamp => Some(i.clamp(0, n - 1) as usize),
+            BorderMode::Wrap => Some(i.rem_euclid(n) as usize),
+            BorderMode::Mirror => {
+                if n == 1 {
+                    return Some(0);
+                }
+                let period = 2 * (n - 1);
+                let i = i.rem_euclid(period);
+                Some((if i < n { i } else { period - i }) as usize)
+            }
+        }
+    }
+}
+
+/// A dense 2D convolution kernel with odd dimensions, row-major.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    pub width : usize,
+    pub height : usize,
+    pub weights : Vec<f32>,
+}
+
+impl Kernel {
+    pub fn new(width : usize, height : usize, weights : Vec<f32>) -> Self {
+        if width % 2 == 0 || height % 2 == 0 {
+            panic!("kernel dimensions must be odd, got {}x{}", width, height);
+        }
+        if weights.len() != width * height {
+            panic!("a {}x{} kernel needs {} weights but got {}", width, height, width * height, weights.len());
+        }
+        Kernel { width, height, weights }
+    }
+
+    /// Horizontal gradient, positive where the image gets brighter to the
+    /// right.
+    pub fn sobel_x() -> Self {
+        Kernel::new(3, 3, vec![
+            -1.0, 0.0, 1.0,
+            -2.0, 0.0, 2.0,
+            -1.0, 0.0, 1.0,
+        ])
+    }
+
+    /// Vertical gradient, positive where the image gets brighter downwards.
+    pub fn sobel_y() -> Self {
+        Kernel::new(3, 3, vec![
+            -1.0, -2.0, -1.0,
+            0.0, 0.0, 0.0,
+            1.0, 2.0, 1.0,
+        ])
+    }
+
+    pub fn sharpen() -> Self {
+        Kernel::new(3, 3, vec![
+            0.0, -1.0, 0.0,
+            -1.0, 5.0, -1.0,
+            0.0, -1.0, 0.0,
+        ])
+    }
+}
+
+/// A kernel that is the outer product of a horizontal and a vertical one, so
+/// it can be applied as two 1D passes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeparableKernel {
+    pub horizontal : Vec<f32>,
+    pub vertical : Vec<f32>,
+}
+
+impl SeparableKernel {
+    pub fn new(horizontal : Vec<f32>, vertical : Vec<f32>) -> Self {
+        if horizontal.len() % 2 == 0 || vertical.len() % 2 == 0 {
+            panic!("kernel lengths must be odd, got {} and {}", horizontal.len(), vertical.len());
+        }
+        SeparableKernel { horizontal, vertical }
+    }
+
+    /// A normalized Gaussian reaching out to three standard deviations.
+    pub fn gaussian(sigma : f32) -> Self {
+        let radius = (3.0 * sigma).ceil().max(0.0) as isize;
+        let mut weights : Vec<f32> = (-radius..=radius)
+            .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let sum : f32 = weights.iter().sum();
+        weights.iter_mut().for_each(|w| *w /= sum);
+        SeparableKernel::new(weights.clone(), weights)
+    }
+
+    pub fn box_blur(radius : usize) -> Self {
+        let n = 2 * radius + 1;
+        let weights = vec![1.0 / n as f32; n];
+        SeparableKernel::new(weights.clone(), weights)
+    }
+
+    /// The equivalent dense kernel.
+    pub fn to_kernel(&self) -> Kernel {
+        let weights = self.vertical.iter()
+            .flat_map(|v| self.horizontal.iter().map(move |h| v * h))
+            .collect();
+        Kernel::new(self.horizontal.len(), self.vertical.len(), weights)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ResizeFilter {
+    Nearest,
+    #[default]
+    Bilinear,
+    // windowed sinc with three lobes, sharpest but can ring
+    Lanczos3,
+}
+
+/// A row-major image of linear colours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    pub width : usize,
+    pub height : usize,
+    pub pixels : Vec<Color>,
+}
+
+impl Image {
+    /// A black image.
+    pub fn new(width : usize, height : usize) -> Self {
+        Image::filled(width, height, Color::new(0.0, 0.0, 0.0))
+    }
+
+    pub fn filled(width : usize, height : usize, color : Color) -> Self {
+        Image { width, height, pixels : vec![color; width * height] }
+    }
+
+    pub fn from_pixels(width : usize, height : usize, pixels : Vec<Color>) -> Self {
+        if pixels.len() != width * height {
+            panic!("a {}x{} image needs {} pixels but got {}", width, height, width * height, pixels.len());
+        }
+        Image { width, height, pixels }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pixels.is_empty()
+    }
+
+    pub fn get(&self, x : usize, y : usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x : usize, y : usize, color : Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// The pixel at `(x, y)`, which may lie outside the image.
+    pub fn get_bordered(&self, x : isize, y : isize, border : BorderMode) -> Color {
+        match (border.index(x, self.width), border.index(y, self.height)) {
+            (Some(x), Some(y)) => self.get(x, y),
+            _ => match border {
+                BorderMode::Constant(color) => color,
+                _ => unreachable!(),
+            },
+        }
+    }
+
+    pub fn map(&self, f : impl Fn(Color) -> Color + Sync) -> Image {
+        Image::from_pixels(self.width, self.height, self.pixels.par_iter().map(|c| f(*c)).collect())
+    }
+
+    // builds an image row by row on the thread pool
+    fn par_rows(width : usize, height : usize, f : impl Fn(usize, &mut [Color]) + Sync) -> Image {
+        let mut image = Image::new(width, height);
+        if width > 0 {
+            image.pixels.par_chunks_mut(width).enumerate().for_each(|(y, row)| f(y, row));
+        }
+        image
+    }
+
+    pub fn convolve(&self, kernel : &Kernel, border : BorderMode) -> Image {
+        let (rx, ry) = ((kernel.width / 2) as isize, (kernel.height / 2) as isize);
+        Image::par_rows(self.width, self.height, |y, row| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                for ky in 0..kernel.height {
+                    let sy = y as isize + ky as isize - ry;
+                    for kx in 0..kernel.width {
+                        let w = kernel.weights[ky * kernel.width + kx];
+                        if w != 0.0 {
+                            let sx = x as isize + kx as isize - rx;
+                            sum = sum + self.get_bordered(sx, sy, border) * w;
+                        }
+                    }
+                }
+                *out = sum;
+            }
+        })
+    }
+
+    /// Same result as `convolve` with `kernel.to_kernel()`, in two 1D passes.
+    pub fn convolve_separable(&self, kernel : &SeparableKernel, border : BorderMode) -> Image {
+        let rx = (kernel.horizontal.len() / 2) as isize;
+        let horizontal = Image::par_rows(self.width, self.height, |y, row| {
+            for (x, out) in row.iter_mut().enumerate() {
+                *out = kernel.horizontal.iter().enumerate().fold(Color::new(0.0, 0.0, 0.0), |sum, (k, w)| {
+                    sum + self.get_bordered(x as isize + k as isize - rx, y as isize, border) * *w
+                });
+            }
+        });
+        let ry = (kernel.vertical.len() / 2) as isize;
+        Image::par_rows(self.width, self.height, |y, row| {
+            for (x, out) in row.iter_mut().enumerate() {
+                *out = kernel.vertical.iter().enumerate().fold(Color::new(0.0, 0.0, 0.0), |sum, (k, w)| {
+                    sum + horizontal.get_bordered(x as isize, y as isize + k as isize - ry, border) * *w
+                });
+            }
+        })
+    }
+
+    pub fn gaussian_blur(&self, sigma : f32, border : BorderMode) -> Image {
+        self.convolve_separable(&SeparableKernel::gaussian(sigma), border)
+    }
+
+    /// Gradient magnitude from the Sobel operator.
+    pub fn sobel(&self, border : BorderMode) -> Image {
+        let gx = self.convolve(&Kernel::sobel_x(), border);
+        let gy = self.convolve(&Kernel::sobel_y(), border);
+        let pixels = gx.pixels.iter().zip(&gy.pixels).map(|(a, b)| Color::new(
+            a.r.hypot(b.r),
+            a.g.hypot(b.g),
+            a.b.hypot(b.b),
+        )).collect();
+        Image::from_pixels(self.width, self.height, pixels)
+    }
+
+    pub fn resize(&self, width : usize, height : usize, filter : ResizeFilter) -> Image {
+        if self.is_empty() || width == 0 || height == 0 {
+            return Image::new(width, height);
+        }
+        if (width, height) == (self.width, self.height) {
+            return self.clone();
+        }
+        match filter {
+            ResizeFilter::Nearest => self.resize_nearest(width, height),
+            ResizeFilter::Bilinear => self.resize_bilinear(width, height),
+            ResizeFilter::Lanczos3 => {
+                let horizontal = self.resample_rows(width);
+                horizontal.transpose().resample_rows(height).transpose()
+            }
+        }
+    }
+
+    fn resize_nearest(&self, width : usize, height : usize) -> Image {
+        let (sx, sy) = (self.width as f32 / width as f32, self.height as f32 / height as f32);
+        Image::par_rows(width, height, |y, row| {
+            let src_y = (((y as f32 + 0.5) * sy) as usize).min(self.height - 1);
+            for (x, out) in row.iter_mut().enumerate() {
+                let src_x = (((x as f32 + 0.5) * sx) as usize).min(self.width - 1);
+                *out = self.get(src_x, src_y);
+            }
+        })
+    }
+
+    fn resize_bilinear(&self, width : usize, height : usize) -> Image {
+        let (sx, sy) = (self.width as f32 / width as f32, self.height as f32 / height as f32);
+        Image::par_rows(width, height, |y, row| {
+            let fy = (y as f32 + 0.5) * sy - 0.5;
+            let y0 = fy.floor() as isize;
+            let ty = fy - y0 as f32;
+            for (x, out) in row.iter_mut().enumerate() {
+                let fx = (x as f32 + 0.5) * sx - 0.5;
+                let x0 = fx.floor() as isize;
+                let tx = fx - x0 as f32;
+                let at = |x, y| self.get_bordered(x, y, BorderMode::Clamp);
+                let top = at(x0, y0) * (1.0 - tx) + at(x0 + 1, y0) * tx;
+                let bottom = at(x0, y0 + 1) * (1.0 - tx) + at(x0 + 1, y0 + 1) * tx;
+                *out = top * (1.0 - ty) + bottom * ty;
+            }
+        })
+    }
+
+    // Lanczos resampling of every row to `width`, widening the filter when
+    // shrinking so it doesn't alias
+    fn resample_rows(&self, width : usize) -> Image {
+        const LOBES : f32 = 3.0;
+        let scale = self.width as f32 / width as f32;
+        let stretch = scale.max(1.0);
+        let support = LOBES * stretch;
+        Image::par_rows(width, self.height, |y, row| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let center = (x as f32 + 0.5) * scale - 0.5;
+                let first = (center - support).ceil() as isize;
+                let last = (center + support).floor() as isize;
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut total = 0.0;
+                for i in first..=last {
+                    let w = lanczos((i as f32 - center) / stretch, LOBES);
+                    sum = sum + self.get_bordered(i, y as isize, BorderMode::Clamp) * w;
+                    total += w;
+                }
+                *out = if total != 0.0 { sum * (1.0 / total) } else { sum };
+            }
+        })
+    }
+
+    fn transpose(&self) -> Image {
+        Image::par_rows(self.height, self.width, |y, row| {
+            for (x, out) in row.iter_mut().enumerate() {
+                *out = self.get(y, x);
+            }
+        })
+    }
+}
+
+fn lanczos(x : f32, a : f32) -> f32 {
+    if x == 0.0 {
+        return 1.0;
+    }
+    if x.abs() >= a {
+        return 0.0;
+    }
+    let px = std::f32::consts::PI * x;
+    a * px.sin() * (px / a).sin() / (px * px)
+}
+
+impl From<&Texture> for Image {
+    fn from(texture : &Texture) -> Self {
+        Image::from_pixels(texture.width.max(0) as usize, texture.height.max(0) as usize, texture.data.clone())
+    }
+}
+
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} image", self.width, self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(v : f32) -> Color {
+        Color::new(v, v, v)
+    }
+
+    fn ramp(width : usize, height : usize) -> Image {
+        let pixels = (0..width * height).map(|i| gray(((i % width) * 3 + (i / width) * 7) as f32)).collect();
+        Image::from_pixels(width, height, pixels)
+    }
+
+    #[test]
+    fn test_border_modes() {
+        let image = Image::from_pixels(3, 1, vec![gray(0.0), gray(1.0), gray(2.0)]);
+        let at = |x, border| image.get_bordered(x, 0, border).r;
+        assert_eq!(at(-1, BorderMode::Constant(gray(9.0))), 9.0);
+        assert_eq!(at(-2, BorderMode::Clamp), 0.0);
+        assert_eq!(at(4, BorderMode::Wrap), 1.0);
+        assert_eq!(at(-1, BorderMode::Mirror), 1.0);
+        assert_eq!(at(4, BorderMode::Mirror), 0.0);
+    }
+
+    #[test]
+    fn test_separable_matches_dense() {
+        let image = ramp(9, 6);
+        let kernel = SeparableKernel::new(vec![0.25, 0.5, 0.25], vec![0.1, 0.2, 0.4, 0.2, 0.1]);
+        for border in [BorderMode::Clamp, BorderMode::Mirror, BorderMode::Wrap, BorderMode::Constant(gray(0.0))] {
+            let a = image.convolve_separable(&kernel, border);
+            let b = image.convolve(&kernel.to_kernel(), border);
+            for (p, q) in a.pixels.iter().zip(&b.pixels) {
+                assert!((p.r - q.r).abs() < 1e-3, "{:?}", border);
+            }
+        }
+    }
+
+    #[test]
+    fn test_gaussian_keeps_flat_images() {
+        let image = Image::filled(7, 5, gray(0.5));
+        let blurred = image.gaussian_blur(1.5, BorderMode::Clamp);
+        assert!(blurred.pixels.iter().all(|c| (c.r - 0.5).abs() < 1e-5));
+        let sum : f32 = SeparableKernel::gaussian(2.0).horizontal.iter().sum();
+        assert!((sum - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_sobel_finds_edges() {
+        let pixels = (0..36).map(|i| gray(if i % 6 < 3 { 0.0 } else { 1.0 })).collect();
+        let edges = Image::from_pixels(6, 6, pixels).sobel(BorderMode::Clamp);
+        assert_eq!(edges.get(0, 2).r, 0.0);
+        assert!(edges.get(2, 2).r > 1.0);
+        assert_eq!(Image::filled(4, 4, gray(1.0)).convolve(&Kernel::sharpen(), BorderMode::Clamp), Image::filled(4, 4, gray(1.0)));
+    }
+
+    #[test]
+    fn test_resize() {
+        let image = ramp(8, 4);
+        for filter in [ResizeFilter::Nearest, ResizeFilter::Bilinear, ResizeFilter::Lanczos3] {
+            assert_eq!(image.resize(8, 4, filter), image, "{:?}", filter);
+            let small = image.resize(4, 2, filter);
+            assert_eq!((small.width, small.height), (4, 2));
+        }
+        // halving with bilinear averages 2x2 blocks
+        let half = image.resize(4, 2, ResizeFilter::Bilinear);
+        let expected = (image.get(0, 0).r + image.get(1, 0).r + image.get(0, 1).r + image.get(1, 1).r) / 4.0;
+        assert!((half.get(0, 0).r - expected).abs() < 1e-4);
+        let flat = Image::filled(5, 5, gray(0.3)).resize(13, 2, ResizeFilter::Lanczos3);
+        assert!(flat.pixels.iter().all(|c| (c.r - 0.3).abs() < 1e-5));
+    }
+}
@@ -15,17 +15,18 @@ pub struct Material {
 }
 
 impl Material {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(diffuse : Color, specular : Color, k_a : f32, k_d : f32, k_s : f32, alpha : f32, index_of_refraction : f32, n_val : i32, texture : Option<i32>) -> Self {
         Material {
-            diffuse : diffuse,
-            specular : specular,
-            k_a : k_a,
-            k_d : k_d,
-            k_s : k_s,
-            alpha : alpha,
-            index_of_refraction : index_of_refraction,
-            n_val : n_val,
-            texture : texture
+            diffuse,
+            specular,
+            k_a,
+            k_d,
+            k_s,
+            alpha,
+            index_of_refraction,
+            n_val,
+            texture
         }
     }
 }
\ No newline at end of file
@@ -2,4 +2,8 @@ pub mod color;
 pub mod texture;
 pub mod material;
 pub mod light;
-pub mod vec_writer;
\ No newline at end of file
+pub mod vec_writer;
+pub mod tonemap;
+pub mod bloom;
+pub mod image;
+pub mod colorspace;
\ No newline at end of file
@@ -1,8 +1,9 @@
-use zune_jpeg::JpegDecoder;
 use jpeg_encoder;
 use core::fmt;
-use std::fs::read;
 use crate::graphics::color::Color;
+use crate::graphics::image::{BorderMode, Image, Kernel};
+use crate::graphics::tonemap::srgb_encode;
+use crate::input::{self, InputError};
 
 /*
 const GAUSSIAN_KERNEL: [f32; 9] = [
@@ -17,31 +18,35 @@ pub struct Texture {
     pub height : i32,
     pub data : Vec<Color>,
     pub filename : String,
+    // per texel coverage, for images with an alpha channel
+    pub alpha : Option<Vec<f32>>,
 }
 
 impl Texture {
-    pub fn new(filename : &str) -> Self {
-        let file_contents = read(filename).unwrap();
-        let mut decoder = JpegDecoder::new(&file_contents);
-        let data = decoder.decode().unwrap();
-        let image_info = decoder.info().unwrap();
-        // convert Vec<u8> to Vec<Color>
-        let color_data = data
-            .chunks(3)
-            .map(|c| Color::new(c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0))
-            .collect::<Vec<Color>>();
+    /// Loads a JPEG, PNG, TGA, BMP or Radiance HDR file, telling them apart
+    /// by their first bytes. Colours are decoded to linear, so 8 bit images
+    /// lose their sRGB curve.
+    pub fn new(filename : &str) -> Result<Self, InputError> {
+        Ok(Self::from_image(filename, input::read_image(filename)?.to_linear()))
+    }
+
+    /// Loads a texture holding data rather than colour, such as a normal or
+    /// bump map, keeping the stored values.
+    pub fn new_data(filename : &str) -> Result<Self, InputError> {
+        Ok(Self::from_image(filename, input::read_image(filename)?))
+    }
+
+    fn from_image(filename : &str, image : input::DecodedImage) -> Self {
         Texture {
-            width : image_info.width as i32,
-            height : image_info.height as i32,
+            width : image.width as i32,
+            height : image.height as i32,
             filename : filename.to_string(),
-            data : color_data
+            data : image.pixels,
+            alpha : image.alpha,
         }
     }
 
-    pub fn get_pixel(&self, mut u : f32, mut v : f32) -> Color {
-        let x;
-        let y;
-
+    fn index(&self, mut u : f32, mut v : f32) -> usize {
         if u > 1.0 {
             u = u.fract();
         }
@@ -49,21 +54,32 @@ impl Texture {
             v = v.fract();
         }
 
-        y = v * (self.height - 1) as f32;
-        x = u * (self.width - 1) as f32;
+        let y = v * (self.height - 1) as f32;
+        let x = u * (self.width - 1) as f32;
         if y * self.width as f32 + x >= self.data.len() as f32 {
             panic!("invalid texture coordinates");
         }
+        (y * self.width as f32 + x) as usize
+    }
 
-        self.data[(y * self.width as f32 + x) as usize]
+    pub fn get_pixel(&self, u : f32, v : f32) -> Color {
+        self.data[self.index(u, v)]
+    }
+
+    /// Coverage at `(u, v)`, 1.0 for textures without alpha.
+    pub fn get_alpha(&self, u : f32, v : f32) -> f32 {
+        match &self.alpha {
+            Some(alpha) => alpha[self.index(u, v)],
+            None => 1.0,
+        }
     }
 
     pub fn write_to_file(&self, filename : &str) {
         let mut image = Vec::new();
         for color in &self.data {
-            image.push((color.r * 255.0) as u8);
-            image.push((color.g * 255.0) as u8);
-            image.push((color.b * 255.0) as u8);
+            image.push((srgb_encode(color.r.clamp(0.0, 1.0)) * 255.0).round() as u8);
+            image.push((srgb_encode(color.g.clamp(0.0, 1.0)) * 255.0).round() as u8);
+            image.push((srgb_encode(color.b.clamp(0.0, 1.0)) * 255.0).round() as u8);
         }
         let encoder = jpeg_encoder::Encoder::new_file(filename, 100).unwrap();
         encoder.encode(&image, self.width as u16, self.height as u16, jpeg_encoder::ColorType::Rgb).unwrap();
@@ -85,51 +101,22 @@ impl Texture {
             width : self.width + 2,
             height : self.height + 2,
             filename : self.filename.clone(),
-            data : padded_data
+            data : padded_data,
+            alpha : None
         }
     }
 
-    fn apply_kernel(&self, kernel: &Vec<f32>, x: i32, y: i32, kernel_size: usize) -> Color {
-        let mut color = Color::new(0.0, 0.0, 0.0);
-        let half_k = (kernel_size / 2) as i32;
-        let mut sum_r = 0.0;
-        let mut sum_g = 0.0;
-        let mut sum_b = 0.0;
-
-        for ky in -half_k..=half_k {
-            for kx in -half_k..=half_k {
-                let px = (x + kx).clamp(0, self.width - 1);
-                let py = (y + ky).clamp(0, self.height - 1);
-                let pixel = self.data[(py * self.width + px) as usize];
-                let kernel_value = kernel[((ky + half_k) * kernel_size as i32 + (kx + half_k)) as usize];
-
-                sum_r += pixel.r * kernel_value;
-                sum_g += pixel.g * kernel_value;
-                sum_b += pixel.b * kernel_value;
-            }
-        }
-
-        color.r = sum_r;
-        color.g = sum_g;
-        color.b = sum_b;
-        color
-    }
-
-    pub fn filter(&self, kernel : &Vec<f32>, kernel_size: usize) -> Self {
-        let mut filtered_data = Vec::new();
-        let padded_texture = self.pad();
-        println!("Applying filter to texture: {}", self.filename);
-        for y in 1..padded_texture.height-1 {
-            for x in 1..padded_texture.width-1 {
-                let color = padded_texture.apply_kernel(&kernel, x, y, kernel_size);
-                filtered_data.push(color);
-            }
-        }
+    /// Convolves the texture with a square `kernel_size` kernel, black past
+    /// the edges. See `Image` for other borders and separable kernels.
+    pub fn filter(&self, kernel : &[f32], kernel_size: usize) -> Self {
+        let kernel = Kernel::new(kernel_size, kernel_size, kernel.to_vec());
+        let filtered = Image::from(self).convolve(&kernel, BorderMode::Constant(Color::new(0.0, 0.0, 0.0)));
         Texture {
             width : self.width,
             height : self.height,
             filename : self.filename.clone(),
-            data : filtered_data
+            data : filtered.pixels,
+            alpha : self.alpha.clone()
         }
     }
 }
@@ -144,29 +131,46 @@ impl fmt::Display for Texture {
 mod tests {
     use super::*;
 
+    const WALZ: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/../images/walz.jpeg");
+
     #[test]
     fn test_texture_creation() {
-        let texture = Texture::new("images/walz.jpeg");
+        let texture = Texture::new(WALZ).unwrap();
         assert!(texture.width > 0);
         assert!(texture.height > 0);
         assert!(!texture.data.is_empty());
     }
 
+    #[test]
+    fn test_colour_textures_are_linear() {
+        let texture = Texture::new(WALZ).unwrap();
+        let data = Texture::new_data(WALZ).unwrap();
+        // decoding the sRGB curve darkens the midtones
+        let (linear, stored) = (texture.get_pixel(0.5, 0.5), data.get_pixel(0.5, 0.5));
+        assert!(linear.g <= stored.g);
+        assert!((srgb_encode(linear.g) - stored.g).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_missing_texture_is_an_error() {
+        assert!(matches!(Texture::new("no/such/texture.png"), Err(InputError::Io(_))));
+    }
+
     #[test]
     fn test_invalid_texture_coordinates() {
-        let texture = Texture::new("images/walz.jpeg");
+        let texture = Texture::new(WALZ).unwrap();
         assert!(texture.get_pixel(2.0, 2.0) == texture.get_pixel(0.0, 0.0)) // This should panic
     }
 
     #[test]
     fn test_valid_texture_coordinates() {
-        let texture = Texture::new("images/walz.jpeg");
+        let texture = Texture::new(WALZ).unwrap();
         texture.get_pixel(0.5, 0.5); // This should not panic
     }
 
     #[test]
     fn test_texture_wrapping_coordinates() {
-        let texture = Texture::new("images/walz.jpeg");
+        let texture = Texture::new(WALZ).unwrap();
         texture.get_pixel(1.5, 1.5); // This should wrap around and not panic
     }
 }
@@ -0,0 +1,256 @@
+use core::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::graphics::color::Color;
+use crate::region::Region;
+
+/// Compresses linear HDR radiance into the 0.0 to 1.0 display range.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ToneMapper {
+    // cut everything above 1.0
+    #[default]
+    Clamp,
+    Reinhard,
+    // John Hable's Uncharted 2 curve
+    Filmic,
+    // Krzysztof Narkowicz's fit of the ACES reference rendering transform
+    Aces,
+}
+
+impl ToneMapper {
+    pub fn map(&self, color : Color) -> Color {
+        let color = Color::new(finite(color.r), finite(color.g), finite(color.b));
+        match self {
+            ToneMapper::Clamp => color,
+            ToneMapper::Reinhard => {
+                // on luminance so saturated colours keep their hue
+                let l = color.luminance();
+                if l <= 0.0 {
+                    return color;
+                }
+                color * (1.0 / (1.0 + l))
+            }
+            ToneMapper::Filmic => {
+                const WHITE : f32 = 11.2;
+                let white_scale = 1.0 / hable(WHITE);
+                // exposure bias from the original presentation
+                Color::new(hable(2.0 * color.r), hable(2.0 * color.g), hable(2.0 * color.b)) * white_scale
+            }
+            ToneMapper::Aces => Color::new(aces(color.r), aces(color.g), aces(color.b)),
+        }
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(ToneMapper::Clamp),
+            "reinhard" => Ok(ToneMapper::Reinhard),
+            "filmic" => Ok(ToneMapper::Filmic),
+            "aces" => Ok(ToneMapper::Aces),
+            _ => Err(format!("unknown tone mapper: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ToneMapper::Clamp => "clamp",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::Filmic => "filmic",
+            ToneMapper::Aces => "aces",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// NaN and negative light have no meaning on a display
+fn finite(v : f32) -> f32 {
+    if v.is_nan() {
+        0.0
+    } else {
+        v.max(0.0)
+    }
+}
+
+fn hable(x : f32) -> f32 {
+    const A : f32 = 0.15;
+    const B : f32 = 0.50;
+    const C : f32 = 0.10;
+    const D : f32 = 0.20;
+    const E : f32 = 0.02;
+    const F : f32 = 0.30;
+    ((x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F)) - E / F
+}
+
+fn aces(x : f32) -> f32 {
+    (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+}
+
+/// The sRGB transfer function, linear to encoded.
+pub fn srgb_encode(v : f32) -> f32 {
+    if v <= 0.0031308 {
+        12.92 * v
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// The inverse sRGB transfer function, encoded to linear.
+pub fn srgb_decode(v : f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// cheap integer hash giving a repeatable value in [0, 1) per pixel
+fn hash(mut x : u32) -> f32 {
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7feb352d);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846ca68b);
+    x ^= x >> 16;
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+/// How linear radiance becomes 8 bit display values: tone mapping, sRGB
+/// encoding, then quantization with optional dithering.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct DisplayTransform {
+    #[serde(default)]
+    pub tone_mapper : ToneMapper,
+    #[serde(default = "default_dither")]
+    pub dither : bool,
+}
+
+fn default_dither() -> bool {
+    true
+}
+
+impl Default for DisplayTransform {
+    fn default() -> Self {
+        DisplayTransform {
+            tone_mapper : ToneMapper::default(),
+            dither : default_dither(),
+        }
+    }
+}
+
+impl DisplayTransform {
+    /// Tone mapped and sRGB encoded colour, each channel in 0.0 to 1.0.
+    pub fn encode(&self, color : Color) -> Color {
+        let mapped = self.tone_mapper.map(color);
+        Color::new(
+            srgb_encode(mapped.r.clamp(0.0, 1.0)),
+            srgb_encode(mapped.g.clamp(0.0, 1.0)),
+            srgb_encode(mapped.b.clamp(0.0, 1.0))
+        )
+    }
+
+    /// Quantizes an encoded value. `seed` picks the dither noise, so the same
+    /// pixel and channel always round the same way.
+    pub fn quantize(&self, v : f32, seed : u32) -> u8 {
+        let noise = if self.dither {
+            // triangular noise over two steps hides banding without a
+            // brightness dependent noise floor
+            hash(seed.wrapping_mul(2)) + hash(seed.wrapping_mul(2).wrapping_add(1)) - 1.0
+        } else {
+            0.0
+        };
+        (v * 255.0 + noise + 0.5).floor().clamp(0.0, 255.0) as u8
+    }
+
+    /// 8 bit sRGB bytes for a row-major image.
+    pub fn to_rgb8(&self, pixels : &[Color]) -> Vec<u8> {
+        self.to_rgb8_crop(pixels, Region::new(0, 0, pixels.len() as i32, 1), pixels.len() as i32)
+    }
+
+    /// `to_rgb8` for the pixels of `crop` in an image `image_width` pixels
+    /// wide. The dither is seeded by where each pixel sits in the whole image,
+    /// so a crop quantizes exactly like the same pixels of the full render.
+    pub fn to_rgb8_crop(&self, pixels : &[Color], crop : Region, image_width : i32) -> Vec<u8> {
+        let mut image = Vec::with_capacity(pixels.len() * 3);
+        for (i, color) in pixels.iter().enumerate() {
+            let encoded = self.encode(*color);
+            let (x, y) = (crop.x + i as i32 % crop.width, crop.y + i as i32 / crop.width);
+            let seed = (y as u32).wrapping_mul(image_width as u32).wrapping_add(x as u32).wrapping_mul(3);
+            image.push(self.quantize(encoded.r, seed));
+            image.push(self.quantize(encoded.g, seed + 1));
+            image.push(self.quantize(encoded.b, seed + 2));
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_round_trip() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        assert!((srgb_encode(0.5) - 0.7354).abs() < 1e-3);
+        for v in [0.001, 0.2, 0.5, 0.9] {
+            assert!((srgb_decode(srgb_encode(v)) - v).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_tone_mappers_stay_in_range() {
+        for mapper in [ToneMapper::Reinhard, ToneMapper::Filmic, ToneMapper::Aces] {
+            let mut last = 0.0;
+            for i in 0..10 {
+                let v = i as f32 * 0.5;
+                let mapped = mapper.map(Color::new(v, v, v)).r;
+                assert!(mapped >= last, "{} is not monotonic", mapper);
+                assert!(mapped <= 1.05, "{} maps {} to {}", mapper, v, mapped);
+                last = mapped;
+            }
+            // very bright values end up close to white rather than blowing up
+            let bright = mapper.map(Color::new(1000.0, 1000.0, 1000.0)).r;
+            assert!(bright > 0.9 && bright < 1.5, "{} maps 1000 to {}", mapper, bright);
+        }
+    }
+
+    #[test]
+    fn test_negative_and_nan_are_black() {
+        let display = DisplayTransform { dither : false, ..DisplayTransform::default() };
+        assert_eq!(display.to_rgb8(&[Color::new(-1.0, f32::NAN, 2.0)]), vec![0, 0, 255]);
+    }
+
+    #[test]
+    fn test_dither_is_repeatable_and_unbiased() {
+        let display = DisplayTransform::default();
+        let pixels = vec![Color::new(0.2, 0.2, 0.2); 4096];
+        let a = display.to_rgb8(&pixels);
+        assert_eq!(a, display.to_rgb8(&pixels));
+        let expected = srgb_encode(0.2) * 255.0;
+        let mean = a.iter().map(|v| *v as f32).sum::<f32>() / a.len() as f32;
+        assert!((mean - expected).abs() < 0.1);
+        // should not all round to the same value
+        assert!(a.iter().any(|v| *v != a[0]));
+    }
+
+    #[test]
+    fn test_crop_dithers_like_the_full_image() {
+        let display = DisplayTransform::default();
+        let pixels : Vec<Color> = (0..64).map(|i| Color::new(0.2, 0.3, i as f32 / 64.0)).collect();
+        let full = display.to_rgb8(&pixels);
+        // the 3 x 2 block at (2, 5) of an 8 x 8 image
+        let crop = Region::new(2, 5, 3, 2);
+        let cropped : Vec<Color> = [42, 43, 44, 50, 51, 52].iter().map(|i| pixels[*i]).collect();
+        let bytes = display.to_rgb8_crop(&cropped, crop, 8);
+        for (j, i) in [42, 43, 44, 50, 51, 52].into_iter().enumerate() {
+            assert_eq!(bytes[j * 3..j * 3 + 3], full[i * 3..i * 3 + 3]);
+        }
+    }
+}
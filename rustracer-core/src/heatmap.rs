@@ -0,0 +1,193 @@
+use core::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+
+use crate::graphics::color::Color;
+use crate::graphics::tonemap::srgb_decode;
+use crate::raytracer::Raytracer;
+use crate::region::Region;
+use crate::stats::thread_intersection_tests;
+
+/// What a heatmap measures for each pixel.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CostMetric {
+    // microseconds spent tracing the pixel
+    Time,
+    IntersectionTests,
+}
+
+impl CostMetric {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            CostMetric::Time => "us",
+            CostMetric::IntersectionTests => "tests",
+        }
+    }
+}
+
+impl FromStr for CostMetric {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "time" => Ok(CostMetric::Time),
+            "tests" | "intersection_tests" => Ok(CostMetric::IntersectionTests),
+            _ => Err(format!("unknown heatmap metric: {}, expected time or tests", s)),
+        }
+    }
+}
+
+impl fmt::Display for CostMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CostMetric::Time => "time",
+            CostMetric::IntersectionTests => "tests",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// stops of a black, purple, orange, pale yellow ramp in sRGB
+const RAMP : [(f32, f32, f32); 5] = [
+    (0.0, 0.0, 0.016),
+    (0.341, 0.063, 0.431),
+    (0.737, 0.216, 0.329),
+    (0.976, 0.557, 0.035),
+    (0.988, 1.0, 0.643),
+];
+
+fn ramp(t : f32) -> Color {
+    let x = t.clamp(0.0, 1.0) * (RAMP.len() - 1) as f32;
+    let i = (x as usize).min(RAMP.len() - 2);
+    let f = x - i as f32;
+    let (a, b) = (RAMP[i], RAMP[i + 1]);
+    Color::new(
+        srgb_decode(a.0 + (b.0 - a.0) * f),
+        srgb_decode(a.1 + (b.1 - a.1) * f),
+        srgb_decode(a.2 + (b.2 - a.2) * f),
+    )
+}
+
+/// The cost of every pixel of a render, row-major.
+pub struct Heatmap {
+    pub metric : CostMetric,
+    pub values : Vec<f32>,
+}
+
+impl Heatmap {
+    pub fn max(&self) -> f32 {
+        self.values.iter().copied().fold(0.0, f32::max)
+    }
+
+    pub fn mean(&self) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        self.values.iter().sum::<f32>() / self.values.len() as f32
+    }
+
+    /// The value the colour ramp tops out at: the 99th percentile, so a few
+    /// outliers (like the first pixel a thread traces) don't wash out the rest.
+    pub fn scale(&self) -> f32 {
+        let mut sorted = self.values.clone();
+        sorted.sort_by(f32::total_cmp);
+        match sorted.get(sorted.len() * 99 / 100) {
+            Some(v) if *v > 0.0 => *v,
+            _ => self.max(),
+        }
+    }
+
+    /// A false colour picture of the costs, from black for free pixels to
+    /// pale yellow for the most expensive. The values are linear, ready for
+    /// the usual display transform.
+    pub fn false_color(&self) -> Vec<Color> {
+        let scale = self.scale();
+        self.values.iter()
+            .map(|v| ramp(if scale > 0.0 { v / scale } else { 0.0 }))
+            .collect()
+    }
+}
+
+impl fmt::Display for Heatmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.metric.unit();
+        write!(f, "pixel cost: mean {:.2} {}, max {:.2} {}, colour scale tops out at {:.2} {}", self.mean(), unit, self.max(), unit, self.scale(), unit)
+    }
+}
+
+/// Renders `region` like `Raytracer::trace_region_covered`, also recording
+/// what each pixel cost by `metric`.
+pub fn render(raytracer : &Raytracer, region : Region, metric : CostMetric) -> (Region, Vec<Color>, Vec<f32>, Heatmap) {
+    let region = match region.intersect(&Region::full(raytracer.scene.resolution)) {
+        Some(region) => region,
+        None => return (Region::new(region.x, region.y, 0, 0), Vec::new(), Vec::new(), Heatmap { metric, values : Vec::new() }),
+    };
+    let (pixels, (coverage, values)) : (Vec<Color>, (Vec<f32>, Vec<f32>)) = raytracer
+        .map_pixels(region, |x, y| {
+            // a pixel is traced start to finish on one thread
+            let (start, tests) = (Instant::now(), thread_intersection_tests());
+            let (color, alpha) = raytracer.trace_pixel_covered(x, y);
+            let cost = match metric {
+                CostMetric::Time => start.elapsed().as_secs_f32() * 1e6,
+                CostMetric::IntersectionTests => (thread_intersection_tests() - tests) as f32,
+            };
+            (color, (alpha, cost))
+        })
+        .into_iter()
+        .unzip();
+    (region, pixels, coverage, Heatmap { metric, values })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::light::Light;
+    use crate::graphics::material::Material;
+    use crate::math::sphere::Sphere;
+    use crate::math::vector::Vector;
+    use crate::scene::Scene;
+
+    #[test]
+    fn test_intersection_test_costs() {
+        let red = Material::new(Color::new(1.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0), 0.2, 0.6, 0.2, 1.0, 0.0, 10, None);
+        let blue = Material::new(Color::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0), 0.2, 0.6, 0.2, 1.0, 0.0, 10, None);
+        let scene = Scene::new(
+            vec![red, blue],
+            vec![Sphere::new(Vector::new(0.0, 0.0, -8.0, 1.0), 2.0, 0), Sphere::new(Vector::new(0.0, 0.0, -50.0, 1.0), 1.0, 1)],
+            vec![Light::new(Vector::new(0.0, 5.0, 0.0, 1.0), (1.0, 0.0, 0.0), 1.0)],
+            Vec::new(),
+            Vector::new(0.0, 0.0, 0.0, 1.0),
+            Vector::new(0.0, 0.0, -1.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0, 0.0),
+            45.0,
+            (16, 12),
+            (0.5, 1.0),
+            (1.0, 10.0),
+            Color::new(0.2, 0.2, 0.2),
+            2.0,
+            Color::new(0.2, 0.2, 0.2),
+            false,
+            String::new()
+        );
+        let raytracer = Raytracer::new(scene);
+        let (region, pixels, _, heatmap) = render(&raytracer, Region::new(-4, 0, 100, 100), CostMetric::IntersectionTests);
+        assert_eq!(region, Region::full((16, 12)));
+        assert_eq!(pixels.len(), heatmap.values.len());
+        // both spheres for the camera ray, plus the other sphere for the
+        // shadow ray where the near one is hit
+        assert_eq!(heatmap.values[0], 2.0);
+        assert_eq!(heatmap.values[6 * 16 + 8], 3.0);
+        assert_eq!(heatmap.max(), 3.0);
+    }
+
+    #[test]
+    fn test_false_color() {
+        let heatmap = Heatmap { metric : CostMetric::Time, values : vec![0.0, 5.0, 10.0] };
+        let colors = heatmap.false_color();
+        assert_eq!(colors[0], ramp(0.0));
+        assert!(colors[2].luminance() > colors[1].luminance());
+        assert!(colors[1].luminance() > colors[0].luminance());
+        assert_eq!("tests".parse::<CostMetric>().unwrap(), CostMetric::IntersectionTests);
+        assert!("memory".parse::<CostMetric>().is_err());
+    }
+}
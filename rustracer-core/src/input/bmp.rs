@@ -0,0 +1,170 @@
+use crate::input::{u16_at, u32_at, DecodedImage};
+
+const BI_RGB : u32 = 0;
+const BI_BITFIELDS : u32 = 3;
+const BI_ALPHABITFIELDS : u32 = 6;
+
+// pulls one channel out of a packed pixel and scales it to 8 bits
+fn channel(value : u32, mask : u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+    // in u64, a 32 bit mask times 255 doesn't fit a u32
+    let v = ((value & mask) >> mask.trailing_zeros()) as u64;
+    let max = (mask >> mask.trailing_zeros()) as u64;
+    (v * 255 / max) as u8
+}
+
+/// Decodes uncompressed or bitfield BMPs of 1, 4, 8, 16, 24 or 32 bits per
+/// pixel. Alpha is only read when the header declares an alpha mask.
+pub fn decode(bytes : &[u8]) -> Result<DecodedImage, String> {
+    let data_offset = u32_at(bytes, 10)? as usize;
+    let header_size = u32_at(bytes, 14)? as usize;
+    let (width, height, bpp, compression) = if header_size == 12 {
+        // OS/2 core header
+        (u16_at(bytes, 18)? as i32, u16_at(bytes, 20)? as i32, u16_at(bytes, 24)?, BI_RGB)
+    } else if header_size >= 40 {
+        (u32_at(bytes, 18)? as i32, u32_at(bytes, 22)? as i32, u16_at(bytes, 28)?, u32_at(bytes, 30)?)
+    } else {
+        return Err(format!("unsupported header size {}", header_size));
+    };
+    if width <= 0 || height == 0 {
+        return Err(format!("invalid dimensions {}x{}", width, height));
+    }
+    // negative height means the rows are stored top down
+    let top_down = height < 0;
+    let (width, height) = (width as usize, height.unsigned_abs() as usize);
+
+    let (masks, has_alpha) = match (compression, bpp) {
+        (BI_RGB, 16) => ([0x7c00, 0x03e0, 0x001f, 0], false),
+        (BI_RGB, _) => ([0x00ff0000, 0x0000ff00, 0x000000ff, 0], false),
+        (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => {
+            let alpha = if header_size >= 56 || compression == BI_ALPHABITFIELDS { u32_at(bytes, 66)? } else { 0 };
+            ([u32_at(bytes, 54)?, u32_at(bytes, 58)?, u32_at(bytes, 62)?, alpha], alpha != 0)
+        }
+        _ => return Err(format!("unsupported compression {} at {} bits per pixel", compression, bpp)),
+    };
+
+    let palette = if bpp <= 8 {
+        let entry_size = if header_size == 12 { 3 } else { 4 };
+        let colors_used = if header_size >= 40 { u32_at(bytes, 46)? as usize } else { 0 };
+        let count = if colors_used == 0 { 1 << bpp } else { colors_used.min(1 << bpp) };
+        let start = 14 + header_size;
+        let table = bytes.get(start..start + count * entry_size).ok_or("unexpected end of file")?;
+        table.chunks(entry_size).map(|c| [c[2], c[1], c[0]]).collect()
+    } else {
+        Vec::new()
+    };
+
+    if !matches!(bpp, 1 | 4 | 8 | 16 | 24 | 32) {
+        return Err(format!("unsupported bit depth {}", bpp));
+    }
+    // rows are padded to a multiple of four bytes
+    let too_large = || format!("image of {}x{} pixels is too large", width, height);
+    let stride = width.checked_mul(bpp as usize).ok_or_else(too_large)?.div_ceil(32) * 4;
+    let end = stride.checked_mul(height).and_then(|size| size.checked_add(data_offset)).ok_or_else(too_large)?;
+    if end > bytes.len() {
+        return Err("unexpected end of file".to_string());
+    }
+    let channels = if has_alpha { 4 } else { 3 };
+    let mut out = Vec::with_capacity(width * height * channels);
+    for y in 0..height {
+        let row_index = if top_down { y } else { height - 1 - y };
+        let start = data_offset + row_index * stride;
+        let row = bytes.get(start..start + stride).ok_or("unexpected end of file")?;
+        for x in 0..width {
+            let (rgb, a) = match bpp {
+                1 | 4 | 8 => {
+                    let bit = x * bpp as usize;
+                    let byte = row[bit / 8];
+                    let index = (byte >> (8 - bpp as usize - bit % 8)) & ((1u16 << bpp) - 1) as u8;
+                    let rgb = *palette.get(index as usize).ok_or(format!("palette index {} out of range", index))?;
+                    (rgb, 255)
+                }
+                24 => ([row[x * 3 + 2], row[x * 3 + 1], row[x * 3]], 255),
+                _ => {
+                    let value = if bpp == 16 {
+                        u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32
+                    } else {
+                        u32::from_le_bytes([row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]])
+                    };
+                    ([channel(value, masks[0]), channel(value, masks[1]), channel(value, masks[2])], channel(value, masks[3]))
+                }
+            };
+            out.extend_from_slice(&rgb);
+            if has_alpha {
+                out.push(a);
+            }
+        }
+    }
+    Ok(DecodedImage::from_u8(width as u32, height as u32, &out, channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a BITMAPINFOHEADER file with the given pixel data and palette
+    fn bmp(width : i32, height : i32, bpp : u16, palette : &[[u8; 4]], data : &[u8]) -> Vec<u8> {
+        let offset = 14 + 40 + palette.len() * 4;
+        let mut bytes = b"BM".to_vec();
+        bytes.extend_from_slice(&((offset + data.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        bytes.extend_from_slice(&(offset as u32).to_le_bytes());
+        bytes.extend_from_slice(&40u32.to_le_bytes());
+        bytes.extend_from_slice(&width.to_le_bytes());
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&bpp.to_le_bytes());
+        bytes.extend_from_slice(&[0; 24]);
+        for entry in palette {
+            bytes.extend_from_slice(entry);
+        }
+        bytes.extend_from_slice(data);
+        bytes
+    }
+
+    #[test]
+    fn test_bmp_24_bit_bottom_up() {
+        // 1x2, rows padded to 4 bytes, bottom row (blue) first
+        let data = [255, 0, 0, 0, 0, 0, 255, 0];
+        let image = decode(&bmp(1, 2, 24, &[], &data)).unwrap();
+        assert_eq!((image.width, image.height), (1, 2));
+        assert_eq!(image.pixels[0].r, 1.0);
+        assert_eq!(image.pixels[1].b, 1.0);
+    }
+
+    #[test]
+    fn test_bmp_paletted_top_down() {
+        let palette = [[0, 0, 0, 0], [0, 255, 0, 0]];
+        // 3x1 at 1 bit per pixel: 0 1 1
+        let image = decode(&bmp(3, -1, 1, &palette, &[0b0110_0000, 0, 0, 0])).unwrap();
+        assert_eq!(image.pixels[0].g, 0.0);
+        assert_eq!(image.pixels[1].g, 1.0);
+        assert_eq!(image.pixels[2].g, 1.0);
+    }
+
+    #[test]
+    fn test_bmp_truncated() {
+        let bytes = bmp(4, 4, 24, &[], &[0; 10]);
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_bmp_full_width_bitfields() {
+        let masks = [0xffffffffu32.to_le_bytes(), 0x0000ff00u32.to_le_bytes(), 0x000000ffu32.to_le_bytes()];
+        let mut bytes = bmp(1, 1, 32, &masks, &[0x00, 0x00, 0x00, 0x80]);
+        bytes[30] = BI_BITFIELDS as u8;
+        let image = decode(&bytes).unwrap();
+        assert!((image.pixels[0].r - 127.0 / 255.0).abs() < 1e-6);
+        assert_eq!(image.pixels[0].g, 0.0);
+    }
+
+    #[test]
+    fn test_bmp_oversized_header() {
+        let bytes = bmp(i32::MAX, i32::MAX, 32, &[], &[0; 16]);
+        assert!(decode(&bytes).is_err());
+        let bytes = bmp(i32::MAX, -i32::MAX, 1, &[[0; 4], [255; 4]], &[0; 16]);
+        assert!(decode(&bytes).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use crate::graphics::colorspace::ColorSpace;
+use crate::input::DecodedImage;
+use crate::output::hdr::from_rgbe;
+
+/// Decodes Radiance RGBE files with the usual `-Y height +X width`
+/// orientation, flat or with run length encoded scanlines.
+pub fn decode(bytes : &[u8]) -> Result<DecodedImage, String> {
+    // the header is text lines up to a blank one, then the resolution line
+    let mut pos = 0;
+    let mut next_line = || -> Result<String, String> {
+        let end = bytes[pos..].iter().position(|b| *b == b'\n').ok_or("unexpected end of header")?;
+        let line = String::from_utf8_lossy(&bytes[pos..pos + end]).trim_end().to_string();
+        pos += end + 1;
+        Ok(line)
+    };
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported pixel format {}", format));
+            }
+        }
+    }
+    let resolution = next_line()?;
+    let fields : Vec<&str> = resolution.split_whitespace().collect();
+    let (height, width) = match fields.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|_| format!("invalid height {}", h))?,
+            w.parse::<usize>().map_err(|_| format!("invalid width {}", w))?,
+        ),
+        _ => return Err(format!("unsupported orientation {}", resolution)),
+    };
+
+    let mut data = &bytes[pos..];
+    // the fewest bytes a scanline can take: runs of up to 127 per component
+    // when it can be run length encoded, four bytes a pixel otherwise
+    let min_scanline = if (8..0x8000).contains(&width) { Some(4 + 8 * width.div_ceil(127)) } else { width.checked_mul(4) };
+    let fits = width.checked_mul(height).is_some()
+        && min_scanline.and_then(|size| size.checked_mul(height)).is_some_and(|size| size <= data.len());
+    if !fits {
+        return Err(format!("{}x{} pixels don't fit in the {} bytes of image data", width, height, data.len()));
+    }
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        data = read_scanline(data, &mut scanline)?;
+        pixels.extend(scanline.iter().map(|rgbe| from_rgbe(*rgbe)));
+    }
+    Ok(DecodedImage { width : width as u32, height : height as u32, pixels, alpha : None, color_space : ColorSpace::LinearRec709 })
+}
+
+// reads one scanline into `out`, returning the rest of the data
+fn read_scanline<'a>(data : &'a [u8], out : &mut [[u8; 4]]) -> Result<&'a [u8], String> {
+    let width = out.len();
+    let truncated = || "unexpected end of file".to_string();
+    let is_rle = (8..0x8000).contains(&width)
+        && data.len() >= 4
+        && data[0] == 2 && data[1] == 2 && data[2] & 0x80 == 0;
+    if !is_rle {
+        let flat = data.get(..width * 4).ok_or_else(truncated)?;
+        for (pixel, rgbe) in out.iter_mut().zip(flat.chunks(4)) {
+            pixel.copy_from_slice(rgbe);
+        }
+        return Ok(&data[width * 4..]);
+    }
+    if ((data[2] as usize) << 8 | data[3] as usize) != width {
+        return Err("scanline width mismatch".to_string());
+    }
+
+    // each of the four components is run length encoded separately
+    let mut data = &data[4..];
+    for component in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let (&count, rest) = data.split_first().ok_or_else(truncated)?;
+            if count > 128 {
+                let n = count as usize - 128;
+                let value = *rest.first().ok_or_else(truncated)?;
+                if x + n > width {
+                    return Err("run overflows scanline".to_string());
+                }
+                for pixel in &mut out[x..x + n] {
+                    pixel[component] = value;
+                }
+                x += n;
+                data = &rest[1..];
+            } else {
+                let n = count as usize;
+                if n == 0 || x + n > width {
+                    return Err("invalid run length".to_string());
+                }
+                let values = rest.get(..n).ok_or_else(truncated)?;
+                for (pixel, value) in out[x..x + n].iter_mut().zip(values) {
+                    pixel[component] = *value;
+                }
+                x += n;
+                data = &rest[n..];
+            }
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::color::Color;
+    use crate::output::hdr::encode;
+
+    #[test]
+    fn test_hdr_round_trip() {
+        // wide enough for run length encoded scanlines
+        let pixels : Vec<Color> = (0..40 * 3).map(|i| Color::new(i as f32 * 0.1, 5.0, if i % 7 == 0 { 100.0 } else { 0.0 })).collect();
+        let image = decode(&encode(&pixels, 40, 3)).unwrap();
+        assert_eq!((image.width, image.height), (40, 3));
+        assert_eq!(image.color_space, ColorSpace::LinearRec709);
+        for (a, b) in image.pixels.iter().zip(&pixels) {
+            assert!((a.r - b.r).abs() <= b.r.max(b.g).max(b.b) / 64.0);
+            assert!((a.b - b.b).abs() <= b.r.max(b.g).max(b.b) / 64.0);
+        }
+    }
+
+    #[test]
+    fn test_hdr_flat_scanlines() {
+        let pixels = vec![Color::new(0.5, 2.0, 8.0); 4];
+        let image = decode(&encode(&pixels, 2, 2)).unwrap();
+        assert!((image.pixels[3].g - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_hdr_oversized_header() {
+        assert!(decode(b"#?RADIANCE\n\n-Y 4000000000 +X 4000000000\n").is_err());
+        assert!(decode(b"#?RADIANCE\n\n-Y 2 +X 18446744073709551615\n").is_err());
+        // a valid header over too little data
+        let mut bytes = encode(&[Color::new(1.0, 1.0, 1.0); 40 * 3], 40, 3);
+        bytes.truncate(bytes.len() - 20);
+        assert!(decode(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_hdr_bad_orientation() {
+        assert!(decode(b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n+Y 2 +X 2\n").is_err());
+    }
+}
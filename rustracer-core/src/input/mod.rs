@@ -0,0 +1,230 @@
+pub mod bmp;
+pub mod hdr;
+pub mod tga;
+
+use core::fmt;
+
+use zune_jpeg::zune_core::colorspace::ColorSpace as JpegColorSpace;
+use zune_jpeg::zune_core::options::DecoderOptions;
+use zune_jpeg::JpegDecoder;
+
+use crate::graphics::color::Color;
+use crate::graphics::colorspace::ColorSpace;
+
+/// Image formats textures can be read from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InputFormat {
+    Jpeg,
+    Png,
+    Tga,
+    Bmp,
+    Hdr,
+}
+
+impl InputFormat {
+    /// Recognizes the format from the first bytes of the file. TGA has no
+    /// signature, so it is the fallback when the header looks plausible.
+    pub fn detect(bytes : &[u8]) -> Option<Self> {
+        if bytes.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(InputFormat::Jpeg)
+        } else if bytes.starts_with(&[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]) {
+            Some(InputFormat::Png)
+        } else if bytes.starts_with(b"BM") {
+            Some(InputFormat::Bmp)
+        } else if bytes.starts_with(b"#?RADIANCE") || bytes.starts_with(b"#?RGBE") {
+            Some(InputFormat::Hdr)
+        } else if tga::looks_like_tga(bytes) {
+            Some(InputFormat::Tga)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for InputFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            InputFormat::Jpeg => "JPEG",
+            InputFormat::Png => "PNG",
+            InputFormat::Tga => "TGA",
+            InputFormat::Bmp => "BMP",
+            InputFormat::Hdr => "Radiance HDR",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug)]
+pub enum InputError {
+    Io(std::io::Error),
+    UnknownFormat,
+    // the file is damaged or uses a feature we don't read
+    Decode(InputFormat, String),
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Io(e) => write!(f, "error reading image: {}", e),
+            InputError::UnknownFormat => write!(f, "unrecognized image format"),
+            InputError::Decode(format, e) => write!(f, "error decoding {} image: {}", format, e),
+        }
+    }
+}
+
+impl std::error::Error for InputError {}
+
+impl From<std::io::Error> for InputError {
+    fn from(e : std::io::Error) -> Self {
+        InputError::Io(e)
+    }
+}
+
+/// A decoded image, row-major from the top left. 8 bit formats are scaled to
+/// 0.0 to 1.0, HDR values are kept as they are. Pixels are still in the
+/// file's `color_space`.
+pub struct DecodedImage {
+    pub width : u32,
+    pub height : u32,
+    pub pixels : Vec<Color>,
+    // coverage from 0.0 to 1.0, only for images with an alpha channel
+    pub alpha : Option<Vec<f32>>,
+    pub color_space : ColorSpace,
+}
+
+impl DecodedImage {
+    /// Builds an image from interleaved 8 bit gray, gray-alpha, RGB or RGBA,
+    /// which is sRGB encoded.
+    pub fn from_u8(width : u32, height : u32, data : &[u8], channels : usize) -> Self {
+        let pixels = data.chunks(channels).map(|c| {
+            let (r, g, b) = if channels < 3 { (c[0], c[0], c[0]) } else { (c[0], c[1], c[2]) };
+            Color::new(r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0)
+        }).collect();
+        let alpha = if channels == 2 || channels == 4 {
+            Some(data.chunks(channels).map(|c| c[channels - 1] as f32 / 255.0).collect())
+        } else {
+            None
+        };
+        DecodedImage { width, height, pixels, alpha, color_space : ColorSpace::Srgb }
+    }
+
+    /// Converts the pixels into the linear working space.
+    pub fn to_linear(mut self) -> Self {
+        if self.color_space != ColorSpace::LinearRec709 {
+            for pixel in &mut self.pixels {
+                *pixel = self.color_space.to_working(*pixel);
+            }
+            self.color_space = ColorSpace::LinearRec709;
+        }
+        self
+    }
+}
+
+pub fn decode(bytes : &[u8]) -> Result<DecodedImage, InputError> {
+    let format = InputFormat::detect(bytes).ok_or(InputError::UnknownFormat)?;
+    let error = |e : String| InputError::Decode(format, e);
+    match format {
+        InputFormat::Jpeg => {
+            let options = DecoderOptions::default().jpeg_set_out_colorspace(JpegColorSpace::RGB);
+            let mut decoder = JpegDecoder::new_with_options(bytes, options);
+            let data = decoder.decode().map_err(|e| error(format!("{:?}", e)))?;
+            let info = decoder.info().ok_or(error("missing image info".to_string()))?;
+            Ok(DecodedImage::from_u8(info.width as u32, info.height as u32, &data, 3))
+        }
+        InputFormat::Png => {
+            let mut decoder = png::Decoder::new(bytes);
+            // palettes and low bit depths become 8 bit, tRNS becomes alpha
+            decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+            let mut reader = decoder.read_info().map_err(|e| error(e.to_string()))?;
+            let mut data = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut data).map_err(|e| error(e.to_string()))?;
+            data.truncate(info.buffer_size());
+            let channels = info.color_type.samples();
+            Ok(DecodedImage::from_u8(info.width, info.height, &data, channels))
+        }
+        InputFormat::Tga => tga::decode(bytes).map_err(error),
+        InputFormat::Bmp => bmp::decode(bytes).map_err(error),
+        InputFormat::Hdr => hdr::decode(bytes).map_err(error),
+    }
+}
+
+pub fn read_image(filename : &str) -> Result<DecodedImage, InputError> {
+    let bytes = std::fs::read(filename)?;
+    decode(&bytes)
+}
+
+// little endian readers that fail on truncated files instead of panicking
+pub(crate) fn u16_at(bytes : &[u8], offset : usize) -> Result<u16, String> {
+    bytes.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or("unexpected end of file".to_string())
+}
+
+pub(crate) fn u32_at(bytes : &[u8], offset : usize) -> Result<u32, String> {
+    bytes.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or("unexpected end of file".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::tonemap::DisplayTransform;
+    use crate::output::{self, ImageFormat};
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(InputFormat::detect(&[0xff, 0xd8, 0xff, 0xe0]), Some(InputFormat::Jpeg));
+        assert_eq!(InputFormat::detect(b"#?RADIANCE\n"), Some(InputFormat::Hdr));
+        assert_eq!(InputFormat::detect(b"BM\0\0"), Some(InputFormat::Bmp));
+        assert_eq!(InputFormat::detect(b"hello world, not an image"), None);
+        assert!(matches!(decode(b"nope"), Err(InputError::UnknownFormat)));
+    }
+
+    #[test]
+    fn test_decode_png_and_jpeg() {
+        let pixels = vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0), Color::new(0.0, 0.0, 1.0), Color::new(1.0, 1.0, 1.0)];
+        let display = DisplayTransform { dither : false, ..DisplayTransform::default() };
+        let png = output::encode(&pixels, 2, 2, ImageFormat::Png, &display).unwrap();
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert!(image.pixels == pixels);
+        assert!(image.alpha.is_none());
+        assert_eq!(image.color_space, ColorSpace::Srgb);
+
+        let jpeg = output::encode(&vec![Color::new(1.0, 1.0, 1.0); 64], 8, 8, ImageFormat::Jpeg, &display).unwrap();
+        let image = decode(&jpeg).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert!(image.pixels[0].r > 0.95);
+    }
+
+    #[test]
+    fn test_decode_png_alpha() {
+        let mut bytes = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut bytes, 2, 1);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[255, 0, 0, 255, 0, 0, 255, 0]).unwrap();
+        }
+        let image = decode(&bytes).unwrap();
+        assert_eq!(image.alpha, Some(vec![1.0, 0.0]));
+        assert_eq!(image.pixels[1].b, 1.0);
+    }
+
+    #[test]
+    fn test_to_linear() {
+        let image = DecodedImage::from_u8(2, 1, &[188, 188, 188, 255, 0, 0], 3).to_linear();
+        assert!((image.pixels[0].g - 0.5029).abs() < 1e-3);
+        assert_eq!(image.pixels[1], Color::new(1.0, 0.0, 0.0));
+        assert_eq!(image.color_space, ColorSpace::LinearRec709);
+    }
+
+    #[test]
+    fn test_truncated_file_is_an_error() {
+        let pixels = vec![Color::new(0.5, 0.5, 0.5); 16];
+        let png = output::encode(&pixels, 4, 4, ImageFormat::Png, &DisplayTransform::default()).unwrap();
+        assert!(matches!(decode(&png[..png.len() / 2]), Err(InputError::Decode(InputFormat::Png, _))));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::input::{u16_at, DecodedImage};
+
+const HEADER_SIZE : usize = 18;
+
+// image types we read: true colour and grayscale, raw or run length encoded
+fn is_supported_type(image_type : u8) -> bool {
+    matches!(image_type, 2 | 3 | 10 | 11)
+}
+
+/// Whether the header is one we can read. TGA has no magic number so this
+/// is only a plausibility check.
+pub fn looks_like_tga(bytes : &[u8]) -> bool {
+    if bytes.len() < HEADER_SIZE {
+        return false;
+    }
+    let (color_map, image_type, depth) = (bytes[1], bytes[2], bytes[16]);
+    let width = u16::from_le_bytes([bytes[12], bytes[13]]);
+    let height = u16::from_le_bytes([bytes[14], bytes[15]]);
+    color_map == 0 && is_supported_type(image_type) && matches!(depth, 8 | 16 | 24 | 32) && width > 0 && height > 0
+}
+
+/// Decodes 8 bit grayscale and 16, 24 or 32 bit true colour images, raw or
+/// RLE, in either row order.
+pub fn decode(bytes : &[u8]) -> Result<DecodedImage, String> {
+    if !looks_like_tga(bytes) {
+        return Err("unsupported TGA header".to_string());
+    }
+    let id_length = bytes[0] as usize;
+    let image_type = bytes[2];
+    let width = u16_at(bytes, 12)? as usize;
+    let height = u16_at(bytes, 14)? as usize;
+    let depth = bytes[16];
+    let descriptor = bytes[17];
+    let top_down = descriptor & 0x20 != 0;
+    let alpha_bits = descriptor & 0x0f;
+    let bytes_per_pixel = depth.div_ceil(8) as usize;
+
+    let mut data = bytes.get(HEADER_SIZE + id_length..).ok_or("unexpected end of file")?;
+    let count = width * height;
+    // an RLE packet of one pixel can stand for 128 of them
+    let expansion = if image_type >= 9 { 128 } else { 1 };
+    let fits = count.checked_mul(bytes_per_pixel)
+        .is_some_and(|size| size <= data.len().saturating_mul(expansion));
+    if !fits {
+        return Err("unexpected end of file".to_string());
+    }
+    let raw = if image_type >= 9 {
+        let mut raw = Vec::with_capacity(count * bytes_per_pixel);
+        while raw.len() < count * bytes_per_pixel {
+            let (&packet, rest) = data.split_first().ok_or("unexpected end of file")?;
+            let n = (packet & 0x7f) as usize + 1;
+            if packet & 0x80 != 0 {
+                let pixel = rest.get(..bytes_per_pixel).ok_or("unexpected end of file")?;
+                for _ in 0..n {
+                    raw.extend_from_slice(pixel);
+                }
+                data = &rest[bytes_per_pixel..];
+            } else {
+                let pixels = rest.get(..n * bytes_per_pixel).ok_or("unexpected end of file")?;
+                raw.extend_from_slice(pixels);
+                data = &rest[n * bytes_per_pixel..];
+            }
+        }
+        raw.truncate(count * bytes_per_pixel);
+        raw
+    } else {
+        data.get(..count * bytes_per_pixel).ok_or("unexpected end of file")?.to_vec()
+    };
+
+    let has_alpha = depth == 32 || (depth == 16 && alpha_bits > 0);
+    let channels = if has_alpha { 4 } else { 3 };
+    let mut rgba = Vec::with_capacity(count * channels);
+    for y in 0..height {
+        // rows are stored bottom up unless the descriptor says otherwise
+        let row = if top_down { y } else { height - 1 - y };
+        for pixel in raw[row * width * bytes_per_pixel..(row + 1) * width * bytes_per_pixel].chunks(bytes_per_pixel) {
+            let (r, g, b, a) = match depth {
+                8 => (pixel[0], pixel[0], pixel[0], 255),
+                16 => {
+                    let v = u16::from_le_bytes([pixel[0], pixel[1]]);
+                    let five = |shift : u16| (((v >> shift) & 0x1f) as u32 * 255 / 31) as u8;
+                    (five(10), five(5), five(0), if v & 0x8000 != 0 { 255 } else { 0 })
+                }
+                24 => (pixel[2], pixel[1], pixel[0], 255),
+                _ => (pixel[2], pixel[1], pixel[0], pixel[3]),
+            };
+            rgba.extend_from_slice(&[r, g, b]);
+            if has_alpha {
+                rgba.push(a);
+            }
+        }
+    }
+    Ok(DecodedImage::from_u8(width as u32, height as u32, &rgba, channels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::tga::encode;
+
+    #[test]
+    fn test_tga_round_trip() {
+        let rgba = [255, 0, 0, 255, 0, 255, 0, 128, 0, 0, 255, 0, 10, 20, 30, 40];
+        let image = decode(&encode(&rgba, 2, 2, 4)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(image.pixels[1].g, 1.0);
+        assert_eq!(image.alpha.unwrap()[2], 0.0);
+    }
+
+    #[test]
+    fn test_tga_rle_bottom_up() {
+        // 2x2, 24 bit RLE, bottom row first: a run of two blue then two raw
+        let mut bytes = vec![0, 0, 10, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, 24, 0];
+        bytes.extend_from_slice(&[0x81, 255, 0, 0]);
+        bytes.extend_from_slice(&[0x01, 0, 0, 255, 0, 255, 0]);
+        let image = decode(&bytes).unwrap();
+        // the second packet is the top row: red, then green
+        assert_eq!(image.pixels[0].r, 1.0);
+        assert_eq!(image.pixels[1].g, 1.0);
+        assert_eq!(image.pixels[2].b, 1.0);
+        assert_eq!(image.pixels[3].b, 1.0);
+        assert!(image.alpha.is_none());
+    }
+
+    #[test]
+    fn test_tga_oversized_header() {
+        // 65535x65535 at 32 bits, raw and RLE, with a handful of bytes of data
+        for image_type in [2, 10] {
+            let mut bytes = vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 32, 8];
+            bytes.extend_from_slice(&[0xff, 1, 2, 3, 4]);
+            assert!(decode(&bytes).is_err());
+        }
+    }
+}
\ No newline at end of file
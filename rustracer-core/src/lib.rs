@@ -1,4 +1,18 @@
 pub mod math;
 pub mod graphics;
 pub mod scene;
-pub mod raytracer;
\ No newline at end of file
+pub mod raytracer;
+pub mod camera;
+pub mod output;
+pub mod region;
+pub mod animation;
+pub mod aov;
+pub mod denoise;
+pub mod progressive;
+pub mod compare;
+pub mod input;
+pub mod stats;
+pub mod heatmap;
+pub mod sampling;
+pub mod migration;
+pub mod classic;
\ No newline at end of file
@@ -9,12 +9,18 @@ use std::cmp::PartialEq;
 pub struct Ray {
     pub o: Vector,
     pub d: Vector,
+    // moment within the shutter interval the ray samples
+    pub time: f32,
 }
 
 impl Ray {
     pub fn new(o: Vector, mut d: Vector) -> Self {
         d.normalize();
-        Ray { o: o, d: d }
+        Ray { o, d, time: 0.0 }
+    }
+
+    pub fn at_time(self, time: f32) -> Self {
+        Ray { time, ..self }
     }
 
     pub fn get_point(&self, t: f32) -> Vector{
@@ -22,14 +28,15 @@ impl Ray {
     }
 
     pub fn intersect_sphere(&self, sphere : &Sphere) -> f32 {
+        let center = sphere.center_at(self.time);
         let b = 2.0 * 
-            (self.d.x * (self.o.x - sphere.center.x) +
-            self.d.y * (self.o.y - sphere.center.y) +
-            self.d.z * (self.o.z - sphere.center.z));
+            (self.d.x * (self.o.x - center.x) +
+            self.d.y * (self.o.y - center.y) +
+            self.d.z * (self.o.z - center.z));
         let c = 
-            f32::powf(self.o.x - sphere.center.x, 2.0) +
-            f32::powf(self.o.y - sphere.center.y, 2.0) +
-            f32::powf(self.o.z - sphere.center.z, 2.0) -
+            f32::powf(self.o.x - center.x, 2.0) +
+            f32::powf(self.o.y - center.y, 2.0) +
+            f32::powf(self.o.z - center.z, 2.0) -
             f32::powf(sphere.radius, 2.0);
         
         let discrim = f32::powf(b, 2.0) - (4.0 * c);
@@ -95,7 +102,7 @@ impl Ray {
                 let beta = ((d22 * dp1) - (d12 * dp2)) / det;
                 let gamma = ((d11 * dp2) - (d12 * dp1)) / det;
                 let alpha = 1.0 - (beta + gamma);
-                if 0.0 <= alpha && alpha <= 1.0 && 0.0 <= beta && beta <= 1.0 && 0.0 <= gamma && gamma <= 1.0 {
+                if (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta) && (0.0..=1.0).contains(&gamma) {
                     match coords {
                         None => t,
                         Some(ptr) => {
@@ -117,7 +124,7 @@ impl Ray {
         let i = -self.d;
         let mut r = (*n * 2.0 * n.dot(&i)) - i;
         r.normalize();
-        return r;
+        r
     }
 
     pub fn refract(&self, n: &Vector, n1: f32, n2: f32) -> Vector {
@@ -128,7 +135,7 @@ impl Ray {
         let discrim = 1.0 - f32::powf(snell, 2.0) * (1.0 - f32::powf(ndoti, 2.0));
 
         if discrim < 0.0 {
-            return Vector::new(0.0,0.0,0.0,0.0)
+            Vector::new(0.0,0.0,0.0,0.0)
         }
         else {
             let a = -*n * f32::sqrt(discrim);
@@ -140,7 +147,8 @@ impl Ray {
 impl PartialEq for Ray {
     fn eq(&self, other: &Self) -> bool {
         self.o == other.o &&
-        self.d == other.d
+        self.d == other.d &&
+        self.time == other.time
     }
 }
 
@@ -186,6 +194,17 @@ fn test_ray_intersect_sphere() {
     assert!(t > 0.0);
 }
 
+#[test]
+fn test_ray_intersect_moving_sphere() {
+    let origin = Vector::new(4.0, 0.0, -5.0, 1.0);
+    let direction = Vector::new(0.0, 0.0, 1.0, 0.0);
+    let mut sphere = Sphere::new(Vector::new(0.0, 0.0, 0.0, 1.0), 1.0, 0);
+    sphere.end_center = Some(Vector::new(4.0, 0.0, 0.0, 1.0));
+    let ray = Ray::new(origin, direction);
+    assert!(ray.intersect_sphere(&sphere) < 0.0);
+    assert!(ray.at_time(1.0).intersect_sphere(&sphere) > 0.0);
+}
+
 #[test]
 fn test_ray_intersect_plane() {
     let origin = Vector::new(0.0, 0.0, 0.0, 1.0);
@@ -211,7 +230,7 @@ fn test_ray_intersect_triangle() {
     let mut coords = [0.0; 3];
     let t = ray.intersect_triangle(&triangle, Some(&mut coords));
     assert!(t > 0.0);
-    assert!(coords.iter().all(|&c| c >= 0.0 && c <= 1.0));
+    assert!(coords.iter().all(|&c| (0.0..=1.0).contains(&c)));
 }
 
 #[test]
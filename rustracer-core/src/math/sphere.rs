@@ -9,11 +9,21 @@ pub struct Sphere {
     pub center: Vector,
     pub radius: f32,
     pub material_index: usize,
+    // where the sphere has moved to at time 1.0, if it moves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_center: Option<Vector>,
 }
 
 impl Sphere {
     pub fn new(center: Vector, radius: f32, material_index: usize) -> Self {
-        Sphere { center: center, radius: radius, material_index: material_index }
+        Sphere { center, radius, material_index, end_center: None }
+    }
+
+    pub fn center_at(&self, time: f32) -> Vector {
+        match self.end_center {
+            Some(end) => self.center + (end - self.center) * time,
+            None => self.center,
+        }
     }
 }
 
@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
 use wavefront::{Obj, Vertex};
 
+use super::ray::Ray;
 use super::vector::Vector;
 
 #[derive(Copy, Clone, Debug)]
@@ -7,14 +9,18 @@ pub struct Triangle {
     pub position : (Vector, Vector, Vector),
     pub normals : Option<(Vector, Vector, Vector)>,
     pub uvs : Option<([f32; 3], [f32; 3], [f32; 3])>,
+    // meshes loaded from obj files all use material 0
+    pub material_index : usize,
 }
 
 impl Triangle {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(p1: Vector, p2: Vector, p3: Vector, n1: Vector, n2: Vector, n3: Vector, uv1 : [f32; 3], uv2 : [f32; 3], uv3 : [f32; 3]) -> Self {
         Triangle {
             position : (p1, p2, p3),
             normals : Some((n1, n2, n3)),
             uvs : Some((uv1, uv2, uv3)),
+            material_index : 0,
         }
     }
 
@@ -24,31 +30,165 @@ impl Triangle {
             Vector::from_vec(triangle[1].position(), 1.0),
             Vector::from_vec(triangle[2].position(), 1.0)
         );
-        let normals = None;
-        let uvs = None;
+        let mut normals = None;
+        let mut uvs = None;
         if triangle[0].normal().is_some() && triangle[1].normal().is_some() && triangle[2].normal().is_some() {
-            let normals = (
+            normals = Some((
                 Vector::from_vec(triangle[0].normal().unwrap(), 0.0),
                 Vector::from_vec(triangle[1].normal().unwrap(), 0.0),
                 Vector::from_vec(triangle[2].normal().unwrap(), 0.0)
-            );
+            ));
         }
         if triangle[0].uv().is_some() && triangle[1].uv().is_some() && triangle[2].uv().is_some() {
-            let uvs = (
+            uvs = Some((
                 triangle[0].uv().unwrap(),
                 triangle[1].uv().unwrap(),
                 triangle[2].uv().unwrap()
-            );
+            ));
         }
         Triangle {
             position : pos,
-            normals : normals,
-            uvs : uvs,
+            normals,
+            uvs,
+            material_index : 0,
         }
     }
 
+    /// The surface normal at barycentric coordinates `bary`, interpolated from
+    /// the vertex normals when the model has them.
+    pub fn normal_at(&self, bary : [f32; 3]) -> Vector {
+        let mut n = match self.normals {
+            Some((n1, n2, n3)) => (n1 * bary[0]) + (n2 * bary[1]) + (n3 * bary[2]),
+            None => (self.position.1 - self.position.0).cross(&(self.position.2 - self.position.0)),
+        };
+        n.normalize();
+        n
+    }
+
     pub fn from_obj(filename : &str) -> Vec<Triangle>{
         let model = Obj::from_file(filename).unwrap();
         model.triangles().map(Self::from_model).collect()
     }
-}
\ No newline at end of file
+}
+
+fn no_rotation() -> Vector {
+    Vector::new(0.0, 0.0, 0.0, 0.0)
+}
+
+fn unit_scale() -> f32 {
+    1.0
+}
+
+/// Moves the whole mesh from one placement at time 0.0 to another at time
+/// 1.0. The translations are offsets from where the mesh was modelled, the
+/// rotations are angles in degrees about the x, y and z axes through the
+/// model's origin, applied in that order, and the scales are uniform.
+/// Each part is interpolated linearly over the shutter interval.
+#[derive(Copy, Clone, Debug, Serialize, Deserialize)]
+pub struct MeshMotion {
+    pub start : Vector,
+    pub end : Vector,
+    #[serde(default = "no_rotation")]
+    pub start_rotation : Vector,
+    #[serde(default = "no_rotation")]
+    pub end_rotation : Vector,
+    #[serde(default = "unit_scale")]
+    pub start_scale : f32,
+    #[serde(default = "unit_scale")]
+    pub end_scale : f32,
+}
+
+impl MeshMotion {
+    pub fn transform_at(&self, time : f32) -> MeshTransform {
+        MeshTransform {
+            translation : self.start + (self.end - self.start) * time,
+            rotation : self.start_rotation + (self.end_rotation - self.start_rotation) * time,
+            scale : self.start_scale + (self.end_scale - self.start_scale) * time,
+        }
+    }
+}
+
+/// Where the mesh sits at one moment: scaled, then rotated, then translated.
+#[derive(Copy, Clone, Debug)]
+pub struct MeshTransform {
+    pub translation : Vector,
+    // degrees about x, then y, then z
+    pub rotation : Vector,
+    pub scale : f32,
+}
+
+fn rotate_axis(v : Vector, axis : usize, degrees : f32) -> Vector {
+    if degrees == 0.0 {
+        return v;
+    }
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    match axis {
+        0 => Vector::new(v.x, v.y * cos - v.z * sin, v.y * sin + v.z * cos, v.w),
+        1 => Vector::new(v.x * cos + v.z * sin, v.y, -v.x * sin + v.z * cos, v.w),
+        _ => Vector::new(v.x * cos - v.y * sin, v.x * sin + v.y * cos, v.z, v.w),
+    }
+}
+
+impl MeshTransform {
+    fn rotate(&self, v : Vector) -> Vector {
+        let v = rotate_axis(v, 0, self.rotation.x);
+        let v = rotate_axis(v, 1, self.rotation.y);
+        rotate_axis(v, 2, self.rotation.z)
+    }
+
+    fn unrotate(&self, v : Vector) -> Vector {
+        let v = rotate_axis(v, 2, -self.rotation.z);
+        let v = rotate_axis(v, 1, -self.rotation.y);
+        rotate_axis(v, 0, -self.rotation.x)
+    }
+
+    /// `ray` in the space the mesh was modelled in. Distances along it are
+    /// divided by the scale.
+    pub fn to_model(&self, ray : &Ray) -> Ray {
+        let mut o = self.unrotate(ray.o - self.translation) * (1.0 / self.scale);
+        o.w = ray.o.w;
+        Ray { o, d : self.unrotate(ray.d), ..*ray }
+    }
+
+    /// A normal of the modelled mesh as it faces in the scene.
+    pub fn normal_to_world(&self, n : Vector) -> Vector {
+        self.rotate(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mesh_transform() {
+        let motion : MeshMotion = serde_json::from_str(r#"{
+            "start": {"x": 0, "y": 0, "z": 0, "w": 0},
+            "end": {"x": 2, "y": 0, "z": 0, "w": 0},
+            "end_rotation": {"x": 0, "y": 0, "z": 180, "w": 0},
+            "end_scale": 3
+        }"#).unwrap();
+        let transform = motion.transform_at(0.5);
+        assert_eq!(transform.scale, 2.0);
+        // the model point (1, 0, 0) is scaled to 2, turned 90 degrees to (0, 2)
+        // and moved 1 along x
+        let ray = Ray::new(Vector::new(1.0, 2.0, 5.0, 1.0), Vector::new(0.0, 0.0, -1.0, 0.0));
+        let model_ray = transform.to_model(&ray);
+        let p = model_ray.get_point(5.0 / transform.scale);
+        assert!((p.x - 1.0).abs() < 1e-5 && p.y.abs() < 1e-5 && p.z.abs() < 1e-5);
+        let n = transform.normal_to_world(Vector::new(1.0, 0.0, 0.0, 0.0));
+        assert!(n.x.abs() < 1e-5 && (n.y - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_translation_only_motion_defaults() {
+        let motion : MeshMotion = serde_json::from_str(r#"{
+            "start": {"x": 0, "y": 0, "z": 0, "w": 0},
+            "end": {"x": 0, "y": 1, "z": 0, "w": 0}
+        }"#).unwrap();
+        let transform = motion.transform_at(1.0);
+        assert_eq!(transform.scale, 1.0);
+        assert_eq!((transform.rotation.x, transform.rotation.y, transform.rotation.z), (0.0, 0.0, 0.0));
+        assert_eq!(transform.translation.y, 1.0);
+    }
+}
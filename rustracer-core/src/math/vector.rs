@@ -14,11 +14,11 @@ pub struct Vector {
 
 impl Vector {
     pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
-        Vector { x: x, y: y, z: z, w: w }
+        Vector { x, y, z, w }
     }
 
     pub fn from_vec(v : [f32; 3], w : f32) -> Self {
-        Vector { x: v[0], y: v[1], z: v[2], w: w }
+        Vector { x: v[0], y: v[1], z: v[2], w }
     }
 
     pub fn dot(&self, other: &Vector) -> f32 {
@@ -50,12 +50,7 @@ impl Vector {
     }
 
     pub fn is_normalized(&self) -> bool {
-        if f32::abs(self.x * self.x + self.y * self.y + self.z * self.z - 1.0) > f32::EPSILON {
-            false
-        }
-        else {
-            true
-        }
+        f32::abs(self.x * self.x + self.y * self.y + self.z * self.z - 1.0) <= f32::EPSILON
     }
 }
 
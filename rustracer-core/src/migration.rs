@@ -0,0 +1,106 @@
+use serde_json::{Map, Value};
+
+use crate::scene::{Problem, SceneError};
+
+/// The scene layout this build reads and writes.
+pub const CURRENT_VERSION : u32 = 1;
+
+// `MIGRATIONS[n]` upgrades a version n document to version n + 1. Documents
+// without a version predate versioning and are version 0.
+const MIGRATIONS : [fn(&mut Map<String, Value>); CURRENT_VERSION as usize] = [
+    // version 1 only adds the version field itself
+    |_| {},
+];
+
+/// The version a scene document was written for.
+pub fn version(document : &Value) -> Result<u32, SceneError> {
+    match document.get("version") {
+        None => Ok(0),
+        Some(value) => value.as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| SceneError::Invalid(vec![Problem { path : "version".to_string(), message : format!("expected a version number, got {}", value) }])),
+    }
+}
+
+/// Upgrades a scene document to `CURRENT_VERSION`, returning the version it
+/// was at. Documents from newer builds are refused rather than guessed at.
+pub fn migrate(document : &mut Value) -> Result<u32, SceneError> {
+    let from = version(document)?;
+    if from > CURRENT_VERSION {
+        return Err(SceneError::UnsupportedVersion(from));
+    }
+    let Some(fields) = document.as_object_mut() else {
+        // not a scene at all, leave the error to the parser
+        return Ok(from);
+    };
+    for migration in &MIGRATIONS[from as usize..] {
+        migration(fields);
+    }
+    fields.insert("version".to_string(), CURRENT_VERSION.into());
+    Ok(from)
+}
+
+/// The render API used to divide material colours by 255 itself, so clients
+/// wrote them in 0 to 255. Scales them down the same way in documents from
+/// before versioning that don't declare a `color_encoding`.
+pub fn scale_legacy_api_colors(document : &mut Value) {
+    if !matches!(version(document), Ok(0)) || document.get("color_encoding").is_some() {
+        return;
+    }
+    let Some(materials) = document.get_mut("materials").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for material in materials {
+        for key in ["diffuse", "specular"] {
+            let Some(color) = material.get_mut(key).and_then(Value::as_object_mut) else {
+                continue;
+            };
+            for channel in ["r", "g", "b"] {
+                if let Some(v) = color.get(channel).and_then(Value::as_f64) {
+                    color.insert(channel.to_string(), (v / 255.0).into());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_unversioned_documents_are_upgraded() {
+        let mut document = json!({"hfov": 45.0});
+        assert_eq!(migrate(&mut document).unwrap(), 0);
+        assert_eq!(document, json!({"hfov": 45.0, "version": CURRENT_VERSION}));
+        // migrating again changes nothing
+        assert_eq!(migrate(&mut document).unwrap(), CURRENT_VERSION);
+    }
+
+    #[test]
+    fn test_bad_versions() {
+        assert!(matches!(migrate(&mut json!({"version": CURRENT_VERSION + 1})), Err(SceneError::UnsupportedVersion(v)) if v == CURRENT_VERSION + 1));
+        assert!(matches!(migrate(&mut json!({"version": "two"})), Err(SceneError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_legacy_api_colors() {
+        let material = json!({"diffuse": {"r": 255, "g": 51, "b": 0}, "specular": {"r": 255, "g": 255, "b": 255}});
+        let mut document = json!({"materials": [material], "bkg_color": {"r": 0.5, "g": 0.5, "b": 0.5}});
+        scale_legacy_api_colors(&mut document);
+        assert_eq!(document["materials"][0]["diffuse"], json!({"r": 1.0, "g": 0.2, "b": 0.0}));
+        assert_eq!(document["materials"][0]["specular"]["g"], json!(1.0));
+        assert_eq!(document["bkg_color"]["r"], json!(0.5));
+        // versioned documents and ones that say how their colours are written
+        // are left alone
+        for mut document in [
+            json!({"version": 1, "materials": [material]}),
+            json!({"color_encoding": {"scale": 255}, "materials": [material]}),
+        ] {
+            let before = document.clone();
+            scale_legacy_api_colors(&mut document);
+            assert_eq!(document, before);
+        }
+    }
+}
@@ -0,0 +1,128 @@
+use std::io::Cursor;
+
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, ImageAttributes, IntegerBounds, Layer, LayerAttributes, SmallVec, Vec2, WritableImage, f16};
+
+use crate::graphics::color::Color;
+use super::OutputError;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ExrPrecision {
+    // 16 bit floats, plenty for colour and half the size
+    #[default]
+    Half,
+    Float,
+}
+
+/// A named group of channels. A single layer may have an empty name, in a
+/// multi-part file an unnamed layer is called "beauty" instead.
+pub struct ExrLayer {
+    pub name : String,
+    pub channels : Vec<(String, Vec<f32>)>,
+}
+
+impl ExrLayer {
+    pub fn rgb(name : &str, pixels : &[Color]) -> Self {
+        ExrLayer {
+            name : name.to_string(),
+            channels : vec![
+                ("R".to_string(), pixels.iter().map(|c| c.r).collect()),
+                ("G".to_string(), pixels.iter().map(|c| c.g).collect()),
+                ("B".to_string(), pixels.iter().map(|c| c.b).collect()),
+            ],
+        }
+    }
+
+    /// Premultiplied colour with an alpha channel.
+    pub fn rgba(name : &str, pixels : &[Color], alpha : &[f32]) -> Self {
+        let mut layer = Self::rgb(name, pixels);
+        layer.channels.push(("A".to_string(), alpha.to_vec()));
+        layer
+    }
+
+    pub fn single(name : &str, channel : &str, samples : Vec<f32>) -> Self {
+        ExrLayer {
+            name : name.to_string(),
+            channels : vec![(channel.to_string(), samples)],
+        }
+    }
+}
+
+fn samples(values : &[f32], precision : ExrPrecision) -> FlatSamples {
+    match precision {
+        ExrPrecision::Half => FlatSamples::F16(values.iter().map(|v| f16::from_f32(*v)).collect()),
+        ExrPrecision::Float => FlatSamples::F32(values.to_vec()),
+    }
+}
+
+/// Writes every layer as a part of one multi-part OpenEXR file.
+pub fn encode_layers(layers : &[ExrLayer], width : u32, height : u32, precision : ExrPrecision) -> Result<Vec<u8>, OutputError> {
+    let size = Vec2(width as usize, height as usize);
+    let mut exr_layers = Vec::new();
+    for layer in layers {
+        let mut channels = SmallVec::new();
+        for (name, values) in &layer.channels {
+            if values.len() != size.area() {
+                return Err(OutputError::Encoding(format!("channel {}.{} has {} samples, expected {}", layer.name, name, values.len(), size.area())));
+            }
+            channels.push(AnyChannel::new(name.as_str(), samples(values, precision)));
+        }
+        let attributes = match (layer.name.as_str(), layers.len()) {
+            ("", 1) => LayerAttributes::default(),
+            ("", _) => LayerAttributes::named("beauty"),
+            (name, _) => LayerAttributes::named(name),
+        };
+        exr_layers.push(Layer::new(size, attributes, Encoding::FAST_LOSSLESS, AnyChannels::sort(channels)));
+    }
+
+    let image = Image::from_layers(ImageAttributes::new(IntegerBounds::from_dimensions(size)), exr_layers);
+    let mut cursor = Cursor::new(Vec::new());
+    image.write().to_buffered(&mut cursor).map_err(|e| OutputError::Encoding(e.to_string()))?;
+    Ok(cursor.into_inner())
+}
+
+pub fn encode(pixels : &[Color], width : u32, height : u32, precision : ExrPrecision) -> Result<Vec<u8>, OutputError> {
+    encode_layers(&[ExrLayer::rgb("", pixels)], width, height, precision)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exr::prelude::{read, FlatImage, ReadChannels, ReadLayers, Text};
+
+    fn decode(bytes : Vec<u8>) -> FlatImage {
+        read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .all_channels()
+            .all_layers()
+            .all_attributes()
+            .from_buffered(Cursor::new(bytes))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_exr_keeps_values_above_one() {
+        let pixels = vec![Color::new(4.0, 0.5, 0.0), Color::new(0.0, 0.0, 100.0)];
+        let image = decode(encode(&pixels, 2, 1, ExrPrecision::Float).unwrap());
+        let channels = &image.layer_data[0].channel_data.list;
+        let red = channels.iter().find(|c| c.name == *"R").unwrap();
+        let blue = channels.iter().find(|c| c.name == *"B").unwrap();
+        assert_eq!(red.sample_data.value_by_flat_index(0).to_f32(), 4.0);
+        assert_eq!(blue.sample_data.value_by_flat_index(1).to_f32(), 100.0);
+    }
+
+    #[test]
+    fn test_exr_layers() {
+        let pixels = vec![Color::new(1.0, 1.0, 1.0); 4];
+        let layers = [
+            ExrLayer::rgb("", &pixels),
+            ExrLayer::single("depth", "Z", vec![1.0, 2.0, 3.0, 4.0]),
+        ];
+        let image = decode(encode_layers(&layers, 2, 2, ExrPrecision::Half).unwrap());
+        assert_eq!(image.layer_data.len(), 2);
+        assert_eq!(image.layer_data[0].attributes.layer_name, Some(Text::from("beauty")));
+        let depth = &image.layer_data[1];
+        assert_eq!(depth.attributes.layer_name, Some(Text::from("depth")));
+        assert_eq!(depth.channel_data.list[0].sample_data.value_by_flat_index(3).to_f32(), 4.0);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::graphics::color::Color;
+
+/// Shared exponent encoding used by Radiance. Negative values are clamped to
+/// zero as the format can't store them.
+pub fn to_rgbe(color : &Color) -> [u8; 4] {
+    let r = color.r.max(0.0);
+    let g = color.g.max(0.0);
+    let b = color.b.max(0.0);
+    let v = r.max(g).max(b);
+    if v < 1e-32 || !v.is_finite() {
+        return [0, 0, 0, 0];
+    }
+    // v = m * 2^e with m in [0.5, 1)
+    let e = v.log2().floor() as i32 + 1;
+    let scale = 256.0 / 2.0_f32.powi(e);
+    [
+        (r * scale).min(255.0) as u8,
+        (g * scale).min(255.0) as u8,
+        (b * scale).min(255.0) as u8,
+        (e + 128).clamp(0, 255) as u8,
+    ]
+}
+
+pub fn from_rgbe(rgbe : [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let scale = 2.0_f32.powi(rgbe[3] as i32 - 128 - 8);
+    Color::new(
+        (rgbe[0] as f32 + 0.5) * scale,
+        (rgbe[1] as f32 + 0.5) * scale,
+        (rgbe[2] as f32 + 0.5) * scale
+    )
+}
+
+// run length encodes one component of a scanline
+fn write_component(bytes : &mut Vec<u8>, data : &[u8]) {
+    const MIN_RUN : usize = 4;
+    let mut i = 0;
+    while i < data.len() {
+        // find the next run long enough to be worth encoding
+        let mut run_start = i;
+        let mut run_length = 0;
+        while run_start < data.len() {
+            run_length = 1;
+            while run_start + run_length < data.len() && run_length < 127 && data[run_start + run_length] == data[run_start] {
+                run_length += 1;
+            }
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+        // literal bytes up to the run
+        while i < run_start {
+            let count = (run_start - i).min(128);
+            bytes.push(count as u8);
+            bytes.extend_from_slice(&data[i..i + count]);
+            i += count;
+        }
+        if run_length >= MIN_RUN {
+            bytes.push(128 + run_length as u8);
+            bytes.push(data[run_start]);
+            i = run_start + run_length;
+        }
+    }
+}
+
+/// Radiance RGBE (.hdr) with run length encoded scanlines.
+pub fn encode(pixels : &[Color], width : u32, height : u32) -> Vec<u8> {
+    let mut bytes = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes();
+    let rgbe : Vec<[u8; 4]> = pixels.iter().map(to_rgbe).collect();
+    for row in rgbe.chunks(width.max(1) as usize) {
+        // the scanline encoding only covers these widths
+        if !(8..=0x7fff).contains(&width) {
+            for pixel in row {
+                bytes.extend_from_slice(pixel);
+            }
+            continue;
+        }
+        bytes.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+        for component in 0..4 {
+            let data : Vec<u8> = row.iter().map(|p| p[component]).collect();
+            write_component(&mut bytes, &data);
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgbe_round_trip() {
+        for color in [Color::new(1.0, 0.5, 0.25), Color::new(1000.0, 3.0, 0.0), Color::new(0.001, 0.002, 0.003)] {
+            let decoded = from_rgbe(to_rgbe(&color));
+            for (a, b) in [(decoded.r, color.r), (decoded.g, color.g), (decoded.b, color.b)] {
+                // 8 bits of mantissa relative to the largest component
+                assert!((a - b).abs() <= color.r.max(color.g).max(color.b) / 128.0);
+            }
+        }
+        assert_eq!(to_rgbe(&Color::new(-1.0, 0.0, 0.0)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_hdr_header() {
+        let bytes = encode(&[Color::new(1.0, 1.0, 1.0); 4], 2, 2);
+        let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X 2\n";
+        assert_eq!(&bytes[..header.len()], header);
+        // too narrow for run length encoding
+        assert_eq!(bytes.len(), header.len() + 16);
+    }
+
+    #[test]
+    fn test_hdr_run_length_encoding() {
+        let bytes = encode(&[Color::new(1.0, 1.0, 1.0); 16], 16, 1);
+        let header_len = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 16\n".len();
+        // scanline marker then a single run for each component
+        assert_eq!(&bytes[header_len..header_len + 4], &[2, 2, 0, 16]);
+        assert_eq!(bytes.len(), header_len + 4 + 4 * 2);
+    }
+}
@@ -0,0 +1,311 @@
+pub mod exr;
+pub mod hdr;
+pub mod ppm;
+pub mod qoi;
+pub mod tga;
+
+use core::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::graphics::color::Color;
+use crate::graphics::tonemap::DisplayTransform;
+use crate::graphics::vec_writer::VecWriter;
+use crate::region::Region;
+use exr::{ExrLayer, ExrPrecision};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Ppm,
+    Tga,
+    Qoi,
+    // the formats below keep the unclamped linear values
+    Exr(ExrPrecision),
+    Hdr,
+}
+
+impl ImageFormat {
+    pub fn from_extension(extension : &str) -> Option<Self> {
+        match extension.to_ascii_lowercase().as_str() {
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            "png" => Some(ImageFormat::Png),
+            "ppm" => Some(ImageFormat::Ppm),
+            "tga" => Some(ImageFormat::Tga),
+            "qoi" => Some(ImageFormat::Qoi),
+            "exr" => Some(ImageFormat::Exr(ExrPrecision::default())),
+            "hdr" => Some(ImageFormat::Hdr),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path : &str) -> Option<Self> {
+        Path::new(path).extension()?.to_str().and_then(Self::from_extension)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+            ImageFormat::Tga => "tga",
+            ImageFormat::Qoi => "qoi",
+            ImageFormat::Exr(_) => "exr",
+            ImageFormat::Hdr => "hdr",
+        }
+    }
+
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Ppm => "image/x-portable-pixmap",
+            ImageFormat::Tga => "image/x-tga",
+            ImageFormat::Qoi => "image/qoi",
+            ImageFormat::Exr(_) => "image/x-exr",
+            ImageFormat::Hdr => "image/vnd.radiance",
+        }
+    }
+
+    /// Whether the format stores the pixels it is given without loss. The
+    /// RGBE pixels of Radiance HDR files share one exponent between the
+    /// channels, so a dim channel next to a bright one loses precision.
+    pub fn is_lossless(&self) -> bool {
+        !matches!(self, ImageFormat::Jpeg | ImageFormat::Hdr)
+    }
+
+    /// Whether the format can store an alpha channel.
+    pub fn supports_alpha(&self) -> bool {
+        matches!(self, ImageFormat::Png | ImageFormat::Tga | ImageFormat::Qoi | ImageFormat::Exr(_))
+    }
+
+    /// Whether the format stores floating point values above 1.0.
+    pub fn is_hdr(&self) -> bool {
+        matches!(self, ImageFormat::Exr(_) | ImageFormat::Hdr)
+    }
+}
+
+impl FromStr for ImageFormat {
+    type Err = OutputError;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        Self::from_extension(s).ok_or(OutputError::UnknownFormat(s.to_string()))
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[derive(Debug)]
+pub enum OutputError {
+    UnknownFormat(String),
+    // the format cannot store an image this size
+    Dimensions(u32, u32),
+    // an alpha channel was asked for in a format without one
+    NoAlpha(ImageFormat),
+    Encoding(String),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for OutputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OutputError::UnknownFormat(s) => write!(f, "unknown image format: {}", s),
+            OutputError::Dimensions(w, h) => write!(f, "image dimensions {}x{} are not supported by this format", w, h),
+            OutputError::NoAlpha(format) => write!(f, "{} images have no alpha channel", format),
+            OutputError::Encoding(e) => write!(f, "error encoding image: {}", e),
+            OutputError::Io(e) => write!(f, "error writing image: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {}
+
+impl From<std::io::Error> for OutputError {
+    fn from(e : std::io::Error) -> Self {
+        OutputError::Io(e)
+    }
+}
+
+// width * height, which can overflow u32 and on 32 bit targets usize
+fn pixel_count(width : u32, height : u32) -> Result<usize, OutputError> {
+    (width as usize).checked_mul(height as usize)
+        .ok_or_else(|| OutputError::Encoding(format!("{}x{} pixels is too many to encode", width, height)))
+}
+
+/// Encodes a row-major `width` x `height` image of linear radiance in
+/// `format`. 8 bit formats go through `display` first, floating point formats
+/// are written as they are.
+pub fn encode(pixels : &[Color], width : u32, height : u32, format : ImageFormat, display : &DisplayTransform) -> Result<Vec<u8>, OutputError> {
+    encode_crop(pixels, Region::new(0, 0, width as i32, height as i32), width as i32, format, display)
+}
+
+/// `encode` for the pixels of `crop` in an image `image_width` pixels wide,
+/// dithered like the same pixels of the whole image.
+pub fn encode_crop(pixels : &[Color], crop : Region, image_width : i32, format : ImageFormat, display : &DisplayTransform) -> Result<Vec<u8>, OutputError> {
+    let (width, height) = (crop.width as u32, crop.height as u32);
+    let n = pixel_count(width, height)?;
+    if pixels.len() != n {
+        return Err(OutputError::Encoding(format!("expected {} pixels but got {}", n, pixels.len())));
+    }
+    let fits_u16 = width <= u16::MAX as u32 && height <= u16::MAX as u32;
+    let rgb = if format.is_hdr() { Vec::new() } else { display.to_rgb8_crop(pixels, crop, image_width) };
+    match format {
+        ImageFormat::Jpeg => {
+            if !fits_u16 {
+                return Err(OutputError::Dimensions(width, height));
+            }
+            let mut bytes = Vec::new();
+            let encoder = jpeg_encoder::Encoder::new(VecWriter::new(&mut bytes), 100);
+            encoder.encode(&rgb, width as u16, height as u16, jpeg_encoder::ColorType::Rgb)
+                .map_err(|e| OutputError::Encoding(e.to_string()))?;
+            Ok(bytes)
+        }
+        ImageFormat::Png => {
+            let mut bytes = Vec::new();
+            {
+                let mut encoder = png::Encoder::new(VecWriter::new(&mut bytes), width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+                let mut writer = encoder.write_header().map_err(|e| OutputError::Encoding(e.to_string()))?;
+                writer.write_image_data(&rgb).map_err(|e| OutputError::Encoding(e.to_string()))?;
+            }
+            Ok(bytes)
+        }
+        ImageFormat::Ppm => Ok(ppm::encode(&rgb, width, height)),
+        ImageFormat::Tga => {
+            if !fits_u16 {
+                return Err(OutputError::Dimensions(width, height));
+            }
+            Ok(tga::encode(&rgb, width, height, 3))
+        }
+        ImageFormat::Qoi => Ok(qoi::encode(&rgb, width, height, 3)),
+        ImageFormat::Exr(precision) => exr::encode(pixels, width, height, precision),
+        ImageFormat::Hdr => Ok(hdr::encode(pixels, width, height)),
+    }
+}
+
+/// Encodes an image with an alpha channel. `pixels` are premultiplied by
+/// `coverage`, which is how EXR stores them; the 8 bit formats get straight
+/// alpha.
+pub fn encode_rgba(pixels : &[Color], coverage : &[f32], width : u32, height : u32, format : ImageFormat, display : &DisplayTransform) -> Result<Vec<u8>, OutputError> {
+    encode_crop_rgba(pixels, coverage, Region::new(0, 0, width as i32, height as i32), width as i32, format, display)
+}
+
+/// `encode_rgba` for the pixels of `crop` in an image `image_width` pixels
+/// wide, like `encode_crop`.
+pub fn encode_crop_rgba(pixels : &[Color], coverage : &[f32], crop : Region, image_width : i32, format : ImageFormat, display : &DisplayTransform) -> Result<Vec<u8>, OutputError> {
+    let (width, height) = (crop.width as u32, crop.height as u32);
+    let n = pixel_count(width, height)?;
+    if pixels.len() != n || coverage.len() != n {
+        return Err(OutputError::Encoding(format!("expected {} pixels and coverage values but got {} and {}", n, pixels.len(), coverage.len())));
+    }
+    if let ImageFormat::Exr(precision) = format {
+        return exr::encode_layers(&[ExrLayer::rgba("", pixels, coverage)], width, height, precision);
+    }
+    if !format.supports_alpha() {
+        return Err(OutputError::NoAlpha(format));
+    }
+    let straight : Vec<Color> = pixels.iter().zip(coverage)
+        .map(|(color, alpha)| if *alpha > 0.0 { *color * (1.0 / alpha) } else { *color })
+        .collect();
+    let rgb = display.to_rgb8_crop(&straight, crop, image_width);
+    let mut rgba = Vec::with_capacity(rgb.len() / 3 * 4);
+    for (color, alpha) in rgb.chunks(3).zip(coverage) {
+        rgba.extend_from_slice(color);
+        rgba.push((alpha.clamp(0.0, 1.0) * 255.0).round() as u8);
+    }
+    match format {
+        ImageFormat::Png => {
+            let mut bytes = Vec::new();
+            {
+                let mut encoder = png::Encoder::new(VecWriter::new(&mut bytes), width, height);
+                encoder.set_color(png::ColorType::Rgba);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+                let mut writer = encoder.write_header().map_err(|e| OutputError::Encoding(e.to_string()))?;
+                writer.write_image_data(&rgba).map_err(|e| OutputError::Encoding(e.to_string()))?;
+            }
+            Ok(bytes)
+        }
+        ImageFormat::Tga => {
+            if width > u16::MAX as u32 || height > u16::MAX as u32 {
+                return Err(OutputError::Dimensions(width, height));
+            }
+            Ok(tga::encode(&rgba, width, height, 4))
+        }
+        _ => Ok(qoi::encode(&rgba, width, height, 4)),
+    }
+}
+
+/// Encodes the image and writes it to `filename`.
+pub fn write_image(filename : &str, pixels : &[Color], width : u32, height : u32, format : ImageFormat, display : &DisplayTransform) -> Result<(), OutputError> {
+    let bytes = encode(pixels, width, height, format, display)?;
+    std::fs::write(filename, bytes)?;
+    Ok(())
+}
+
+/// Encodes the image with its alpha channel and writes it to `filename`.
+pub fn write_image_rgba(filename : &str, pixels : &[Color], coverage : &[f32], width : u32, height : u32, format : ImageFormat, display : &DisplayTransform) -> Result<(), OutputError> {
+    let bytes = encode_rgba(pixels, coverage, width, height, format, display)?;
+    std::fs::write(filename, bytes)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ImageFormat::from_path("out/render.PNG"), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::from_path("render.jpeg"), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::from_path("render"), None);
+        assert!("bmp".parse::<ImageFormat>().is_err());
+        assert_eq!("qoi".parse::<ImageFormat>().unwrap(), ImageFormat::Qoi);
+        assert_eq!(ImageFormat::from_path("beauty.exr"), Some(ImageFormat::Exr(ExrPrecision::Half)));
+        assert!(ImageFormat::Hdr.is_hdr());
+        assert!(!ImageFormat::Hdr.is_lossless());
+        assert!(!ImageFormat::Jpeg.is_lossless());
+        assert!(ImageFormat::Png.is_lossless());
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let pixels = vec![Color::new(1.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0), Color::new(0.0, 0.0, 1.0), Color::new(0.5, 0.5, 0.5)];
+        let display = DisplayTransform::default();
+        let bytes = encode(&pixels, 2, 2, ImageFormat::Png, &display).unwrap();
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (2, 2));
+        assert_eq!(&data[..info.buffer_size()], &display.to_rgb8(&pixels)[..]);
+    }
+
+    #[test]
+    fn test_encode_checks_pixel_count() {
+        let pixels = vec![Color::new(0.0, 0.0, 0.0); 3];
+        assert!(encode(&pixels, 2, 2, ImageFormat::Ppm, &DisplayTransform::default()).is_err());
+        // more pixels than fit a u32
+        assert!(encode(&pixels, 70_000, 70_000, ImageFormat::Ppm, &DisplayTransform::default()).is_err());
+        assert!(encode_rgba(&pixels, &[1.0; 3], 70_000, 70_000, ImageFormat::Png, &DisplayTransform::default()).is_err());
+    }
+
+    #[test]
+    fn test_rgba_is_straight_alpha() {
+        // half covered red, premultiplied
+        let pixels = vec![Color::new(0.5, 0.0, 0.0), Color::new(0.0, 0.0, 0.0)];
+        let display = DisplayTransform { dither : false, ..DisplayTransform::default() };
+        let bytes = encode_rgba(&pixels, &[0.5, 0.0], 2, 1, ImageFormat::Png, &display).unwrap();
+        let image = crate::input::decode(&bytes).unwrap();
+        assert_eq!(image.alpha, Some(vec![128.0 / 255.0, 0.0]));
+        assert_eq!(image.pixels[0].r, 1.0);
+        assert!(matches!(encode_rgba(&pixels, &[0.5, 0.0], 2, 1, ImageFormat::Jpeg, &display), Err(OutputError::NoAlpha(_))));
+    }
+}
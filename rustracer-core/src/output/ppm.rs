@@ -0,0 +1,18 @@
+/// Binary (P6) portable pixmap. Only RGB is supported by the format.
+pub fn encode(rgb : &[u8], width : u32, height : u32) -> Vec<u8> {
+    let mut bytes = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    bytes.extend_from_slice(rgb);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ppm_encode() {
+        let bytes = encode(&[255, 0, 0, 0, 255, 0], 2, 1);
+        assert_eq!(&bytes[..11], b"P6\n2 1\n255\n");
+        assert_eq!(&bytes[11..], &[255, 0, 0, 0, 255, 0]);
+    }
+}
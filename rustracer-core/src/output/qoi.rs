@@ -0,0 +1,99 @@
+// see https://qoiformat.org/qoi-specification.pdf
+const QOI_OP_INDEX : u8 = 0x00;
+const QOI_OP_DIFF : u8 = 0x40;
+const QOI_OP_LUMA : u8 = 0x80;
+const QOI_OP_RUN : u8 = 0xc0;
+const QOI_OP_RGB : u8 = 0xfe;
+const QOI_OP_RGBA : u8 = 0xff;
+const END_MARKER : [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+fn hash(p : [u8; 4]) -> usize {
+    (p[0] as usize * 3 + p[1] as usize * 5 + p[2] as usize * 7 + p[3] as usize * 11) % 64
+}
+
+/// Encodes `channels` (3 or 4) bytes per pixel in RGB(A) order as QOI,
+/// tagged as sRGB with linear alpha.
+pub fn encode(pixels : &[u8], width : u32, height : u32, channels : usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(14 + pixels.len() + END_MARKER.len());
+    bytes.extend_from_slice(b"qoif");
+    bytes.extend_from_slice(&width.to_be_bytes());
+    bytes.extend_from_slice(&height.to_be_bytes());
+    bytes.push(channels as u8);
+    bytes.push(0);
+
+    let mut index = [[0u8; 4]; 64];
+    let mut prev = [0, 0, 0, 255];
+    let mut run = 0;
+    let count = pixels.len() / channels;
+    for (i, chunk) in pixels.chunks(channels).enumerate() {
+        let px = [chunk[0], chunk[1], chunk[2], if channels == 4 { chunk[3] } else { 255 }];
+        if px == prev {
+            run += 1;
+            if run == 62 || i == count - 1 {
+                bytes.push(QOI_OP_RUN | (run - 1));
+                run = 0;
+            }
+            continue;
+        }
+        if run > 0 {
+            bytes.push(QOI_OP_RUN | (run - 1));
+            run = 0;
+        }
+
+        let h = hash(px);
+        if index[h] == px {
+            bytes.push(QOI_OP_INDEX | h as u8);
+        } else {
+            index[h] = px;
+            if px[3] == prev[3] {
+                let dr = px[0].wrapping_sub(prev[0]) as i8;
+                let dg = px[1].wrapping_sub(prev[1]) as i8;
+                let db = px[2].wrapping_sub(prev[2]) as i8;
+                let dr_dg = dr.wrapping_sub(dg);
+                let db_dg = db.wrapping_sub(dg);
+                if (-2..=1).contains(&dr) && (-2..=1).contains(&dg) && (-2..=1).contains(&db) {
+                    bytes.push(QOI_OP_DIFF | (((dr + 2) as u8) << 4) | (((dg + 2) as u8) << 2) | (db + 2) as u8);
+                } else if (-32..=31).contains(&dg) && (-8..=7).contains(&dr_dg) && (-8..=7).contains(&db_dg) {
+                    bytes.push(QOI_OP_LUMA | (dg + 32) as u8);
+                    bytes.push((((dr_dg + 8) as u8) << 4) | (db_dg + 8) as u8);
+                } else {
+                    bytes.extend_from_slice(&[QOI_OP_RGB, px[0], px[1], px[2]]);
+                }
+            } else {
+                bytes.extend_from_slice(&[QOI_OP_RGBA, px[0], px[1], px[2], px[3]]);
+            }
+        }
+        prev = px;
+    }
+    bytes.extend_from_slice(&END_MARKER);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qoi_header() {
+        let bytes = encode(&[0, 0, 0, 255], 1, 1, 4);
+        assert_eq!(&bytes[..4], b"qoif");
+        assert_eq!(u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]), 1);
+        assert_eq!(bytes[12], 4);
+        assert!(bytes.ends_with(&END_MARKER));
+    }
+
+    #[test]
+    fn test_qoi_runs() {
+        // a black image is one long run of the starting pixel
+        let bytes = encode(&[0; 3 * 100], 10, 10, 3);
+        assert_eq!(&bytes[14..16], &[QOI_OP_RUN | 61, QOI_OP_RUN | 37]);
+        assert_eq!(bytes.len(), 14 + 2 + END_MARKER.len());
+    }
+
+    #[test]
+    fn test_qoi_small_differences() {
+        let bytes = encode(&[1, 1, 1, 200, 10, 10], 2, 1, 3);
+        assert_eq!(bytes[14], QOI_OP_DIFF | (3 << 4) | (3 << 2) | 3);
+        assert_eq!(&bytes[15..19], &[QOI_OP_RGB, 200, 10, 10]);
+    }
+}
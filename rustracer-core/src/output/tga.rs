@@ -0,0 +1,42 @@
+/// Uncompressed true colour TGA with a top-left origin. `pixels` holds
+/// `channels` (3 or 4) bytes per pixel in RGB(A) order.
+pub fn encode(pixels : &[u8], width : u32, height : u32, channels : usize) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(18 + pixels.len());
+    bytes.push(0); // no image id
+    bytes.push(0); // no colour map
+    bytes.push(2); // uncompressed true colour
+    bytes.extend_from_slice(&[0; 5]); // colour map spec
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // x origin
+    bytes.extend_from_slice(&0u16.to_le_bytes()); // y origin
+    bytes.extend_from_slice(&(width as u16).to_le_bytes());
+    bytes.extend_from_slice(&(height as u16).to_le_bytes());
+    bytes.push((channels * 8) as u8);
+    // bit 5 puts the first row at the top, the low bits count alpha bits
+    let alpha_bits = if channels == 4 { 8 } else { 0 };
+    bytes.push(0x20 | alpha_bits);
+
+    for pixel in pixels.chunks(channels) {
+        bytes.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        if channels == 4 {
+            bytes.push(pixel[3]);
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tga_encode() {
+        let bytes = encode(&[10, 20, 30, 40, 50, 60], 2, 1, 3);
+        assert_eq!(bytes.len(), 18 + 6);
+        assert_eq!(bytes[2], 2);
+        assert_eq!(u16::from_le_bytes([bytes[12], bytes[13]]), 2);
+        assert_eq!(u16::from_le_bytes([bytes[14], bytes[15]]), 1);
+        assert_eq!(bytes[16], 24);
+        // stored as BGR
+        assert_eq!(&bytes[18..], &[30, 20, 10, 60, 50, 40]);
+    }
+}
@@ -0,0 +1,211 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::graphics::color::Color;
+use crate::raytracer::Raytracer;
+use crate::region::Region;
+
+/// Stops a render from another thread, see `Raytracer::cancellation`.
+/// Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled : Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+/// When to stop adding passes. With neither limit set the render runs until
+/// it is cancelled.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Budget {
+    pub passes : Option<u32>,
+    pub time : Option<Duration>,
+}
+
+impl Budget {
+    pub fn is_spent(&self, passes : u32, elapsed : Duration) -> bool {
+        self.passes.is_some_and(|max| passes >= max) || self.time.is_some_and(|max| elapsed >= max)
+    }
+}
+
+/// The running average of all passes so far.
+pub struct Accumulator {
+    pub pixels : Vec<Color>,
+    // the fraction of samples that hit something
+    pub coverage : Vec<f32>,
+    pub passes : u32,
+}
+
+impl Accumulator {
+    pub fn new(size : usize) -> Self {
+        Accumulator {
+            pixels : vec![Color::new(0.0, 0.0, 0.0); size],
+            coverage : vec![0.0; size],
+            passes : 0,
+        }
+    }
+
+    pub fn add(&mut self, pass : &[Color], coverage : &[f32]) {
+        if pass.len() != self.pixels.len() {
+            panic!("pass has {} pixels but the accumulator holds {}", pass.len(), self.pixels.len());
+        }
+        if coverage.len() != self.pixels.len() {
+            panic!("pass has {} coverage values but the accumulator holds {}", coverage.len(), self.pixels.len());
+        }
+        self.passes += 1;
+        let weight = 1.0 / self.passes as f32;
+        for (average, color) in self.pixels.iter_mut().zip(pass) {
+            *average = *average * (1.0 - weight) + *color * weight;
+        }
+        for (average, alpha) in self.coverage.iter_mut().zip(coverage) {
+            *average = *average * (1.0 - weight) + *alpha * weight;
+        }
+    }
+}
+
+/// What a progressive render hands to its callback after every pass.
+pub struct Progress<'a> {
+    pub region : Region,
+    // the average so far, before exposure
+    pub pixels : &'a [Color],
+    pub coverage : &'a [f32],
+    pub passes : u32,
+    pub elapsed : Duration,
+}
+
+/// Renders `region` one sample per pixel at a time, calling `on_pass` with
+/// the running average after each pass, until `budget` is spent or the
+/// raytracer's cancellation token is cancelled. A pass cut short by the
+/// cancel is thrown away, unless it is the first. Returns the clipped region
+/// and the final average.
+pub fn render(raytracer : &Raytracer, region : Region, budget : Budget, mut on_pass : impl FnMut(Progress)) -> (Region, Accumulator) {
+    let token = &raytracer.cancellation;
+    let start = Instant::now();
+    let (region, first, coverage) = raytracer.trace_pass_covered(region, 0);
+    let mut accumulator = Accumulator::new(first.len());
+    accumulator.add(&first, &coverage);
+    loop {
+        let elapsed = start.elapsed();
+        on_pass(Progress { region, pixels : &accumulator.pixels, coverage : &accumulator.coverage, passes : accumulator.passes, elapsed });
+        if region.is_empty() || token.is_cancelled() || budget.is_spent(accumulator.passes, elapsed) {
+            break;
+        }
+        let (_, pass, coverage) = raytracer.trace_pass_covered(region, accumulator.passes);
+        if token.is_cancelled() {
+            break;
+        }
+        accumulator.add(&pass, &coverage);
+    }
+    (region, accumulator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graphics::light::Light;
+    use crate::graphics::material::Material;
+    use crate::math::sphere::Sphere;
+    use crate::math::vector::Vector;
+    use crate::scene::Scene;
+
+    fn raytracer() -> Raytracer {
+        let material = Material::new(Color::new(1.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0), 0.2, 0.6, 0.2, 1.0, 0.0, 10, None);
+        Raytracer::new(Scene::new(
+            vec![material],
+            vec![Sphere::new(Vector::new(0.0, 0.0, -8.0, 1.0), 2.0, 0)],
+            vec![Light::new(Vector::new(0.0, 5.0, 0.0, 1.0), (1.0, 0.0, 0.0), 1.0)],
+            Vec::new(),
+            Vector::new(0.0, 0.0, 0.0, 1.0),
+            Vector::new(0.0, 0.0, -1.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0, 0.0),
+            45.0,
+            (16, 12),
+            (1.0, 1.0),
+            (0.0, 0.0),
+            Color::new(0.2, 0.2, 0.2),
+            2.0,
+            Color::new(0.0, 0.0, 0.0),
+            false,
+            String::new()
+        ))
+    }
+
+    #[test]
+    fn test_render_stops_when_cancelled() {
+        let raytracer = raytracer();
+        let token = raytracer.cancellation.clone();
+        let mut calls = 0;
+        let (_, accumulator) = render(&raytracer, Region::full((16, 12)), Budget::default(), |progress| {
+            calls += 1;
+            if progress.passes == 3 {
+                token.cancel();
+            }
+        });
+        assert_eq!((accumulator.passes, calls), (3, 3));
+        // later renders see the cancel before tracing anything
+        let (_, pixels) = raytracer.trace_region(Region::full((16, 12)));
+        assert!(pixels.iter().all(|c| *c == Color::new(0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_render_stops_on_budget() {
+        let raytracer = raytracer();
+        let (region, accumulator) = render(&raytracer, Region::new(0, 0, 8, 8), Budget { passes : Some(5), time : None }, |_| {});
+        assert_eq!((region, accumulator.passes), (Region::new(0, 0, 8, 8), 5));
+        let budget = Budget { passes : None, time : Some(Duration::from_millis(20)) };
+        let start = Instant::now();
+        let (_, accumulator) = render(&raytracer, Region::full((16, 12)), budget, |_| {});
+        assert!(accumulator.passes >= 1);
+        // a pass of this scene takes well under a second
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_accumulator_averages() {
+        let mut accumulator = Accumulator::new(1);
+        for (v, a) in [(1.0, 1.0), (2.0, 0.0), (6.0, 1.0)] {
+            accumulator.add(&[Color::new(v, v, v)], &[a]);
+        }
+        assert_eq!(accumulator.passes, 3);
+        assert!((accumulator.pixels[0].r - 3.0).abs() < 1e-6);
+        assert!((accumulator.coverage[0] - 2.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    #[should_panic(expected = "2 coverage values")]
+    fn test_accumulator_reports_coverage_mismatch() {
+        let mut accumulator = Accumulator::new(1);
+        accumulator.add(&[Color::new(1.0, 1.0, 1.0)], &[1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_budget() {
+        let budget = Budget { passes : Some(4), time : None };
+        assert!(!budget.is_spent(3, Duration::from_secs(100)));
+        assert!(budget.is_spent(4, Duration::ZERO));
+        let budget = Budget { passes : None, time : Some(Duration::from_millis(10)) };
+        assert!(budget.is_spent(1, Duration::from_millis(10)));
+        assert!(!Budget::default().is_spent(1000, Duration::from_secs(1000)));
+    }
+
+    #[test]
+    fn test_cancellation_is_shared() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+}
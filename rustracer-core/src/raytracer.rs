@@ -1,13 +1,35 @@
-use std::f32::EPSILON;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
+use crate::aov::{Aov, AovBuffer};
+use crate::denoise::{DenoiseError, Denoiser, Guides};
 use crate::math::ray::Ray;
 use crate::graphics::color::Color;
 use crate::math::vector::Vector;
+use crate::region::Region;
+use crate::sampling::{Dimension, SampleIndex, Sampler};
 use crate::scene::Scene;
+use crate::progressive::CancellationToken;
+use crate::stats::{self, Counters};
 
 use rayon::prelude::*;
 
+// how many pixels across auto exposure meters a crop on
+const METERING_SIZE : i32 = 64;
+
+// rays per pixel for images with an alpha channel
+const MIN_ALPHA_SAMPLES : u32 = 4;
+
+/// Where a ray meets the scene.
+#[derive(Copy, Clone, Debug)]
+pub struct Hit {
+    pub t: f32,
+    pub point: Vector,
+    pub normal: Vector,
+    pub material_index: usize,
+    // spheres in scene order, then the triangle mesh
+    pub object_index: usize,
+}
+
 pub struct Raytracer {
     pub scene: Scene,
     pub u: Vector,
@@ -20,11 +42,22 @@ pub struct Raytracer {
     pub dv: Vector,
     pub width: f32,
     pub height: f32,
+    pub counters: Counters,
+    pub sampler: Box<dyn Sampler>,
+    // checked before every row and tile, the ones not started yet when it
+    // is cancelled are left black
+    pub cancellation: CancellationToken,
+    // the auto exposure scale, metered on the first call to `expose`
+    metered_scale: OnceLock<f32>,
 }
 
 impl Raytracer {
-    pub fn new(scene: Scene) -> Self{
-        if scene.view_dir.dot(&scene.up_dir) < -0.9 || scene.view_dir.dot(&scene.up_dir) > 0.9 {
+    pub fn new(mut scene: Scene) -> Self{
+        scene.decode_colors();
+        let (mut view_dir, mut up_dir) = (scene.view_dir, scene.up_dir);
+        view_dir.normalize();
+        up_dir.normalize();
+        if view_dir.dot(&up_dir).abs() > 0.9 {
             panic!("View direction and up direction are too close to parallel");
         }
         let mut u = scene.view_dir.cross(&scene.up_dir);
@@ -48,19 +81,24 @@ impl Raytracer {
 
         let dh = (ur - ul) * (1.0 / (scene.resolution.0 as f32 - 1.0));
         let dv = (ll - ul) * (1.0 / (scene.resolution.1 as f32 - 1.0));
+        let scene_sampler = scene.sampler.build(scene.seed);
 
         Self {
-            scene: scene,
-            u: u,
-            v: v,
-            ul: ul,
-            ur: ur,
-            ll: ll,
-            lr: lr,
-            dh: dh,
-            dv: dv,
-            width: width,
-            height: height
+            scene,
+            u,
+            v,
+            ul,
+            ur,
+            ll,
+            lr,
+            dh,
+            dv,
+            width,
+            height,
+            counters : Counters::default(),
+            sampler : scene_sampler,
+            cancellation : CancellationToken::new(),
+            metered_scale : OnceLock::new(),
         }
     }
 
@@ -78,51 +116,83 @@ impl Raytracer {
         (i * alpha_dc) + (self.scene.dc * (1.0 - alpha_dc))
     }
 
-    pub fn trace(&self, ray : Ray) -> Color {
-        let mut output = self.scene.bkg_color;
+    /// The closest surface `ray` hits, if any.
+    pub fn intersect(&self, ray : &Ray) -> Option<Hit> {
+        stats::count_intersection_tests((self.scene.spheres.len() + self.scene.triangles.len()) as u64);
         let mut min_t = f32::INFINITY;
         let mut hit_index = 0;
         let mut hit = (false, "tri");
         for (i, sphere) in self.scene.spheres.iter().enumerate() {
-            let t = ray.intersect_sphere(&sphere);
-            if t > EPSILON {
-                if t < min_t {
-                    min_t = t;
-                    hit_index = i;
-                    hit.0 = true;
-                    hit.1 = "sphere";
-                }
+            let t = ray.intersect_sphere(sphere);
+            if t > f32::EPSILON && t < min_t {
+                min_t = t;
+                hit_index = i;
+                hit.0 = true;
+                hit.1 = "sphere";
             }
         }
         let mut bary = [0.0, 0.0, 0.0];
+        let mut hit_bary = bary;
+        // move the ray instead of every triangle
+        let transform = self.scene.mesh_motion.map(|motion| motion.transform_at(ray.time));
+        let (mesh_ray, mesh_scale) = match transform {
+            Some(transform) => (transform.to_model(ray), transform.scale),
+            None => (*ray, 1.0),
+        };
         for (i, triangle) in self.scene.triangles.iter().enumerate() {
-            let t = ray.intersect_triangle(triangle, Some(&mut bary));
-            if t > EPSILON {
-                if t < min_t {
-                    min_t = t;
-                    hit_index = i;
-                    hit.0 = true;
-                    hit.1 = "tri";
-                }
+            let t = mesh_ray.intersect_triangle(triangle, Some(&mut bary)) * mesh_scale;
+            if t > f32::EPSILON && t < min_t {
+                min_t = t;
+                hit_index = i;
+                hit_bary = bary;
+                hit.0 = true;
+                hit.1 = "tri";
             }
         }
-        if hit.0 {
-            let mut material_index : usize = 0;
-            let intersection = ray.get_point(min_t);
-            let mut n : Vector = Vector::new(0.0, 0.0, 0.0, 0.0);
-            if hit.1 == "sphere" {
-                n = intersection - self.scene.spheres[hit_index].center;
-                material_index = self.scene.spheres[hit_index].material_index;
-                n.normalize();
-            }
-            output = self.shade(material_index, intersection, n, ray);
+        if !hit.0 {
+            return None;
+        }
+
+        let point = ray.get_point(min_t);
+        let (mut normal, material_index, object_index) = if hit.1 == "sphere" {
+            let sphere = &self.scene.spheres[hit_index];
+            (point - sphere.center_at(ray.time), sphere.material_index, hit_index)
+        } else {
+            // the mesh counts as one object after the spheres
+            let triangle = &self.scene.triangles[hit_index];
+            let normal = triangle.normal_at(hit_bary);
+            let normal = transform.map_or(normal, |transform| transform.normal_to_world(normal));
+            let normal = if normal.dot(&ray.d) > 0.0 { -normal } else { normal };
+            (normal, triangle.material_index, self.scene.spheres.len())
+        };
+        normal.normalize();
+        Some(Hit {
+            t: min_t,
+            point,
+            normal,
+            material_index,
+            object_index,
+        })
+    }
+
+    pub fn trace(&self, ray : Ray) -> Color {
+        self.trace_covered(ray).0
+    }
+
+    /// The colour `ray` sees and whether it hit anything. With a transparent
+    /// background a miss is black, so colours are premultiplied by coverage.
+    pub fn trace_covered(&self, ray : Ray) -> (Color, f32) {
+        match self.intersect(&ray) {
+            Some(hit) => (self.shade(hit.material_index, hit.point, hit.normal, ray), 1.0),
+            None if self.scene.transparent_background => (Color::new(0.0, 0.0, 0.0), 0.0),
+            None => (self.scene.bkg_color, 0.0),
         }
-        output
     }
 
     pub fn shade(&self, m: usize, x_p : Vector, normal : Vector, i_ray : Ray) -> Color {
         let material = self.scene.materials[m];
         let mut final_color = material.diffuse * material.k_a;
+        let mut tests = 0;
         for light in &self.scene.lights {
             let is_point = light.v.w == 1.0;
             let mut s_flag = 1.0;
@@ -134,22 +204,23 @@ impl Raytracer {
             };
             let d = x_p.distance(&light.v);
             l.normalize();
-            let r =  Ray::new(x_p, l);
+            let r =  Ray::new(x_p, l).at_time(i_ray.time);
 
             for sphere in &self.scene.spheres {
                 if sphere.material_index == m {
                     continue;
                 }
+                tests += 1;
                 let t = r.intersect_sphere(sphere);
                 let surface_alpha = material.alpha;
                 let is_between = if is_point {
-                    EPSILON < t && t < d
+                    f32::EPSILON < t && t < d
                 }
                 else {
-                    t > EPSILON
+                    t > f32::EPSILON
                 };
                 if is_between {
-                    s_flag = s_flag * (1.0 - surface_alpha);
+                    s_flag *= 1.0 - surface_alpha;
                 }
             }
             let mut i = i_ray.d * -1.0;
@@ -164,33 +235,515 @@ impl Raytracer {
             let specular = material.specular * ndoth.powi(material.n_val) * material.k_s;
             final_color = final_color +  ((diffuse + specular) * light.i * s_flag);
         }
+        stats::count_shadow_rays(self.scene.lights.len() as u64);
+        stats::count_intersection_tests(tests);
         final_color = self.depth_cue(final_color, self.scene.eye_pos.distance(&x_p));
         final_color
     }
 
-    pub fn trace_rays(self: Arc<Self>) -> Vec<Color>{
-        println!("tracing rays...");
-        let is_parallel = self.scene.parallel;
-        let px_width = self.scene.resolution.0;
-        let px_height = self.scene.resolution.1;
+    /// The primary ray through the corner of pixel `(x, y)`, ignoring the lens.
+    pub fn primary_ray(&self, x : i32, y : i32) -> Ray {
+        self.ray_through(x as f32, y as f32)
+    }
+
+    /// The ray through a fractional pixel position on the ideal image plane.
+    pub fn ray_through(&self, px : f32, py : f32) -> Ray {
+        let p = self.ul + (self.dh * px) + (self.dv * py);
+        if self.scene.parallel {
+            Ray::new(p, self.scene.view_dir)
+        } else {
+            Ray::new(self.scene.eye_pos, p - self.scene.eye_pos)
+        }
+    }
+
+    /// The ray leaving the lens for fractional pixel position `(px, py)` of a
+    /// colour channel magnified by `scale`.
+    pub fn lens_ray(&self, px : f32, py : f32, scale : f32) -> Ray {
+        let cx = (self.scene.resolution.0 - 1) as f32 / 2.0;
+        let cy = (self.scene.resolution.1 - 1) as f32 / 2.0;
+        let norm = cx.max(0.5);
+        let (fx, fy) = self.scene.lens.film_position((px - cx) / norm, (py - cy) / norm, scale);
+        self.ray_through(cx + fx * norm, cy + fy * norm)
+    }
+
+    /// The ray leaving the lens at `(px, py)`, before any splitting into
+    /// colour channels.
+    pub fn camera_ray(&self, px : f32, py : f32) -> Ray {
+        if self.scene.lens.is_pinhole() {
+            self.ray_through(px, py)
+        } else {
+            self.lens_ray(px, py, 1.0)
+        }
+    }
+
+    /// Traces fractional pixel position `(px, py)` at `time` through the
+    /// scene's lens, splitting the channels if there is chromatic aberration.
+    pub fn trace_lens(&self, px : f32, py : f32, time : f32) -> Color {
+        self.trace_lens_covered(px, py, time).0
+    }
 
-        let pixel_map: Vec<Color> = (0..px_height)
+    /// `trace_lens` along with the coverage of the ray, or of the green one
+    /// when the channels are split.
+    pub fn trace_lens_covered(&self, px : f32, py : f32, time : f32) -> (Color, f32) {
+        let lens = self.scene.lens;
+        let ray = self.camera_ray(px, py).at_time(time);
+        let ca = lens.chromatic_aberration;
+        let (color, coverage) = if ca == 0.0 {
+            stats::count_primary_rays(1);
+            self.trace_covered(ray)
+        } else {
+            stats::count_primary_rays(3);
+            let red = self.trace(self.lens_ray(px, py, 1.0 + ca).at_time(time));
+            let (green, coverage) = self.trace_covered(ray);
+            let blue = self.trace(self.lens_ray(px, py, 1.0 - ca).at_time(time));
+            (Color::new(red.r, green.g, blue.b), coverage)
+        };
+        if lens.vignetting == 0.0 {
+            return (color, coverage);
+        }
+        let mut axis = self.scene.view_dir;
+        axis.normalize();
+        (color * lens.vignette(ray.d.dot(&axis)), coverage)
+    }
+
+    /// Averages `scene.samples` rays spread by the scene's sampler over the
+    /// shutter interval and the pixel, a square centred on the ray a single
+    /// sample traces at the moment the shutter opens.
+    pub fn trace_pixel(&self, x : i32, y : i32) -> Color {
+        self.trace_pixel_covered(x, y).0
+    }
+
+    /// `trace_pixel` along with the fraction of its rays that hit something.
+    /// With a transparent background every pixel gets at least
+    /// `MIN_ALPHA_SAMPLES` rays, so silhouettes have soft edges in the alpha
+    /// channel even when the scene asks for one sample.
+    pub fn trace_pixel_covered(&self, x : i32, y : i32) -> (Color, f32) {
+        let (open, close) = self.scene.shutter;
+        let n = if self.scene.transparent_background {
+            self.scene.samples.max(MIN_ALPHA_SAMPLES)
+        } else {
+            self.scene.samples.max(1)
+        };
+        if n == 1 {
+            return self.trace_lens_covered(x as f32, y as f32, open);
+        }
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        let mut coverage = 0.0;
+        for s in 0..n {
+            let at = SampleIndex::new(x, y, s, n);
+            let (dx, dy) = self.sampler.sample_2d(&at, Dimension::Pixel);
+            let time = open + (close - open) * self.sampler.sample_1d(&at, Dimension::Time);
+            let (c, a) = self.trace_lens_covered(x as f32 + dx - 0.5, y as f32 + dy - 0.5, time);
+            color = color + c;
+            coverage += a;
+        }
+        (color * (1.0 / n as f32), coverage / n as f32)
+    }
+
+    /// Renders a single tile into `buffer`, which holds the tile's pixels in
+    /// row-major order and must be `tile.area()` long.
+    pub fn trace_tile(&self, tile : Region, buffer : &mut [Color]) {
+        debug_assert_eq!(buffer.len(), tile.area(), "buffer doesn't fit tile {}", tile);
+        if self.cancellation.is_cancelled() {
+            return;
+        }
+        self.counters.record(|| {
+            for (i, color) in buffer.iter_mut().enumerate() {
+                *color = self.trace_pixel(tile.x + i as i32 % tile.width, tile.y + i as i32 / tile.width);
+            }
+        });
+    }
+
+    // `f` for every pixel of `region` in row-major order, a row to a task,
+    // adding the rays each row traced to the counters. Rows after a cancel
+    // get the default.
+    pub(crate) fn map_pixels<T : Send + Default>(&self, region : Region, f : impl Fn(i32, i32) -> T + Sync) -> Vec<T> {
+        (0..region.height)
             .into_par_iter()
-            .flat_map(|i| {
-                let me = Arc::clone(&self);
-                (0..px_width).into_par_iter().map(move |j| {
-                    // this may need to be used
-                    let _index = (i * px_width + j) as usize;
-                    let color = if is_parallel {
-                        me.trace(Ray::new(me.ul + (me.dh * j as f32) + (me.dv * i as f32), me.scene.view_dir))
-                    } else {
-                        me.trace(Ray::new(me.scene.eye_pos, me.ul + (me.dh * j as f32) + (me.dv * i as f32) - me.scene.eye_pos))
-                    };
-                    color
-                })
+            .flat_map_iter(|row| {
+                if self.cancellation.is_cancelled() {
+                    return (0..region.width).map(|_| T::default()).collect();
+                }
+                let y = region.y + row;
+                self.counters.record(|| (region.x..region.x + region.width).map(|x| f(x, y)).collect::<Vec<_>>())
+            })
+            .collect()
+    }
+
+    fn clip(&self, region : Region) -> Option<Region> {
+        region.intersect(&Region::full(self.scene.resolution))
+    }
+
+    /// Renders the part of `region` that lies inside the image. The returned
+    /// buffer is the size of the clipped region, in row-major order.
+    pub fn trace_region(&self, region : Region) -> (Region, Vec<Color>) {
+        let region = match self.clip(region) {
+            Some(region) => region,
+            None => return (Region::new(region.x, region.y, 0, 0), Vec::new()),
+        };
+        let mut pixel_map = vec![Color::new(0.0, 0.0, 0.0); region.area()];
+        pixel_map
+            .par_chunks_mut(region.width as usize)
+            .enumerate()
+            .for_each(|(i, row)| {
+                let row_region = Region::new(region.x, region.y + i as i32, region.width, 1);
+                self.trace_tile(row_region, row);
+            });
+        (region, pixel_map)
+    }
+
+    /// `trace_region` along with each pixel's coverage, for images with an
+    /// alpha channel.
+    pub fn trace_region_covered(&self, region : Region) -> (Region, Vec<Color>, Vec<f32>) {
+        let region = match self.clip(region) {
+            Some(region) => region,
+            None => return (Region::new(region.x, region.y, 0, 0), Vec::new(), Vec::new()),
+        };
+        let (pixel_map, alpha) = self.map_pixels(region, |x, y| self.trace_pixel_covered(x, y))
+            .into_iter()
+            .unzip();
+        (region, pixel_map, alpha)
+    }
+
+    /// Same as `trace_region`, but hands out `tile_size` squares to the worker
+    /// threads instead of rows.
+    pub fn trace_region_tiled(&self, region : Region, tile_size : i32) -> (Region, Vec<Color>) {
+        let region = match self.clip(region) {
+            Some(region) => region,
+            None => return (Region::new(region.x, region.y, 0, 0), Vec::new()),
+        };
+        let tiles: Vec<(Region, Vec<Color>)> = region.tiles(tile_size)
+            .into_par_iter()
+            .map(|tile| {
+                let mut buffer = vec![Color::new(0.0, 0.0, 0.0); tile.area()];
+                self.trace_tile(tile, &mut buffer);
+                (tile, buffer)
             })
             .collect();
-        println!("tracing complete.");
+
+        let mut pixel_map = vec![Color::new(0.0, 0.0, 0.0); region.area()];
+        for (tile, buffer) in tiles {
+            for (row, colors) in buffer.chunks(tile.width as usize).enumerate() {
+                let start = ((tile.y - region.y + row as i32) * region.width + (tile.x - region.x)) as usize;
+                pixel_map[start..start + colors.len()].copy_from_slice(colors);
+            }
+        }
+        (region, pixel_map)
+    }
+
+    /// Renders one sample per pixel of `region`, clipped to the image like
+    /// `trace_region`. Sample `pass` is the `pass`th sample of the scene's
+    /// sampler inside the pixel and the shutter interval, so averaging the
+    /// first n passes gives an antialiased, motion blurred image, the same
+    /// one `trace_region` makes with n samples unless the sampler needs to
+    /// know n up front.
+    pub fn trace_pass(&self, region : Region, pass : u32) -> (Region, Vec<Color>) {
+        let (region, pixel_map, _) = self.trace_pass_covered(region, pass);
+        (region, pixel_map)
+    }
+
+    /// `trace_pass` along with the coverage of each sample.
+    pub fn trace_pass_covered(&self, region : Region, pass : u32) -> (Region, Vec<Color>, Vec<f32>) {
+        let region = match self.clip(region) {
+            Some(region) => region,
+            None => return (Region::new(region.x, region.y, 0, 0), Vec::new(), Vec::new()),
+        };
+        let (open, close) = self.scene.shutter;
+        let (pixel_map, alpha) = self
+            .map_pixels(region, |x, y| {
+                // the number of passes isn't known up front
+                let at = SampleIndex::new(x, y, pass, 0);
+                let (dx, dy) = self.sampler.sample_2d(&at, Dimension::Pixel);
+                let time = open + (close - open) * self.sampler.sample_1d(&at, Dimension::Time);
+                self.trace_lens_covered(x as f32 + dx - 0.5, y as f32 + dy - 0.5, time)
+            })
+            .into_iter()
+            .unzip();
+        (region, pixel_map, alpha)
+    }
+
+    /// Renders the requested passes for `region`, clipped to the image like
+    /// `trace_region`. They come from a single ray through each pixel at the
+    /// moment the shutter opens.
+    pub fn trace_aovs(&self, region : Region, aovs : &[Aov]) -> (Region, Vec<AovBuffer>) {
+        let region = match self.clip(region) {
+            Some(region) => region,
+            None => return (Region::new(region.x, region.y, 0, 0), Vec::new()),
+        };
+        let time = self.scene.shutter.0;
+        let hits: Vec<Option<Hit>> = self.map_pixels(region, |x, y| {
+            stats::count_primary_rays(1);
+            self.intersect(&self.camera_ray(x as f32, y as f32).at_time(time))
+        });
+
+        let mut axis = self.scene.view_dir;
+        axis.normalize();
+        let buffers = aovs.iter().map(|aov| {
+            let pixels = hits.iter().map(|hit| match hit {
+                Some(hit) => {
+                    let depth = (hit.point - self.scene.eye_pos).dot(&axis);
+                    let albedo = self.scene.materials.get(hit.material_index)
+                        .map(|m| m.diffuse)
+                        .unwrap_or(Color::new(0.0, 0.0, 0.0));
+                    aov.value(hit, depth, albedo)
+                }
+                None => aov.background(),
+            }).collect();
+            AovBuffer { aov: *aov, pixels }
+        }).collect();
+        (region, buffers)
+    }
+
+    /// Scales rendered radiance by the scene's camera exposure, if it has one.
+    /// Auto exposure is metered once per raytracer, on a coarse render of the
+    /// whole frame, so crops and progressive previews come out exactly as
+    /// bright as the same pixels of the full render.
+    pub fn expose(&self, pixel_map : &mut [Color]) {
+        let exposure = match &self.scene.exposure {
+            Some(exposure) => exposure,
+            None => return,
+        };
+        let scale = if exposure.auto {
+            *self.metered_scale.get_or_init(|| exposure.scale(&self.metering_pass()))
+        } else {
+            exposure.scale(&[])
+        };
+        for color in pixel_map.iter_mut() {
+            *color = *color * scale;
+        }
+    }
+
+    // a coarse render of the whole frame, at most METERING_SIZE pixels across
+    fn metering_pass(&self) -> Vec<Color> {
+        let (width, height) = self.scene.resolution;
+        let step = (width.max(height) / METERING_SIZE).max(1);
+        let (columns, rows) = ((width + step - 1) / step, (height + step - 1) / step);
+        self.map_pixels(Region::new(0, 0, columns, rows), |x, y| self.trace_pixel(x * step, y * step))
+    }
+
+    /// Denoises a rendered `region`, tracing the albedo, normal and depth
+    /// passes the filter uses to find edges.
+    pub fn denoise(&self, region : Region, pixel_map : &[Color], denoiser : &Denoiser) -> Result<Vec<Color>, DenoiseError> {
+        let (region, buffers) = self.trace_aovs(region, &Guides::AOVS);
+        match Guides::from_aovs(buffers) {
+            Some(guides) => denoiser.apply(pixel_map, region.width, region.height, &guides),
+            None => Ok(pixel_map.to_vec()),
+        }
+    }
+
+    /// Renders and exposes the whole image.
+    pub fn trace_rays(self: Arc<Self>) -> Vec<Color>{
+        let (_, mut pixel_map) = self.trace_region(Region::full(self.scene.resolution));
+        self.expose(&mut pixel_map);
         pixel_map
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::exposure::Exposure;
+    use crate::graphics::colorspace::{ColorEncoding, ColorSpace};
+    use crate::graphics::light::Light;
+    use crate::graphics::material::Material;
+    use crate::math::sphere::Sphere;
+
+    fn test_scene() -> Scene {
+        let material = Material::new(Color::new(1.0, 0.0, 0.0), Color::new(1.0, 1.0, 1.0), 0.2, 0.6, 0.2, 1.0, 0.0, 10, None);
+        Scene::new(
+            vec![material],
+            vec![Sphere::new(Vector::new(0.0, 0.0, -8.0, 1.0), 2.0, 0)],
+            vec![Light::new(Vector::new(0.0, 5.0, 0.0, 1.0), (1.0, 0.0, 0.0), 1.0)],
+            Vec::new(),
+            Vector::new(0.0, 0.0, 0.0, 1.0),
+            Vector::new(0.0, 0.0, -1.0, 0.0),
+            Vector::new(0.0, 1.0, 0.0, 0.0),
+            45.0,
+            (32, 24),
+            (0.5, 1.0),
+            (1.0, 10.0),
+            Color::new(0.2, 0.2, 0.2),
+            2.0,
+            Color::new(0.2, 0.2, 0.2),
+            false,
+            String::new()
+        )
+    }
+
+    #[test]
+    fn test_scene_colours_are_decoded() {
+        let linear = Raytracer::new(test_scene());
+        // the same scene written the way a colour picker would
+        let mut scene = test_scene();
+        scene.color_encoding = ColorEncoding { space : ColorSpace::Srgb, scale : 255.0 };
+        scene.materials[0].diffuse = Color::new(255.0, 0.0, 0.0);
+        scene.materials[0].specular = Color::new(255.0, 255.0, 255.0);
+        scene.bkg_color = Color::new(124.0, 124.0, 124.0);
+        scene.dc = scene.bkg_color;
+        let decoded = Raytracer::new(scene);
+        assert!(decoded.scene.color_encoding.is_working());
+        assert!((decoded.scene.bkg_color.g - 0.2).abs() < 2e-3);
+        let (a, b) = (linear.trace_pixel(16, 12), decoded.trace_pixel(16, 12));
+        assert!((a.r - b.r).abs() < 2e-3 && (a.g - b.g).abs() < 2e-3);
+    }
+
+    #[test]
+    fn test_transparent_background() {
+        let mut scene = test_scene();
+        scene.transparent_background = true;
+        let raytracer = Raytracer::new(scene);
+        let (region, pixels, coverage) = raytracer.trace_region_covered(Region::full((32, 24)));
+        assert_eq!((pixels.len(), coverage.len()), (region.area(), region.area()));
+        assert_eq!((coverage[0], pixels[0].r), (0.0, 0.0));
+        assert_eq!(coverage[12 * 32 + 16], 1.0);
+        // a single render, as the CLI and API make, has soft edges too
+        assert_eq!(raytracer.scene.samples, 1);
+        assert!(coverage.iter().any(|a| *a > 0.0 && *a < 1.0));
+
+        // averaging passes over the silhouette gives partial coverage
+        let mut edges = vec![0.0; region.area()];
+        for pass in 0..16 {
+            let (_, _, coverage) = raytracer.trace_pass_covered(region, pass);
+            for (sum, alpha) in edges.iter_mut().zip(coverage) {
+                *sum += alpha / 16.0;
+            }
+        }
+        assert!(edges.iter().any(|a| *a > 0.1 && *a < 0.9));
+    }
+
+    #[test]
+    fn test_counters() {
+        let raytracer = Raytracer::new(test_scene());
+        let (region, pixels) = raytracer.trace_region(Region::new(0, 0, 32, 24));
+        let stats = raytracer.counters.snapshot();
+        assert_eq!(stats.primary_rays, pixels.len() as u64);
+        // one shadow ray per light for every pixel that hit the sphere
+        let hits = pixels.iter().filter(|c| c.g < 0.19 || c.r > 0.21).count() as u64;
+        assert_eq!(stats.shadow_rays, hits);
+        assert_eq!(stats.intersection_tests, region.area() as u64);
+    }
+
+    #[test]
+    fn test_seeded_renders_are_reproducible() {
+        let mut scene = test_scene();
+        scene.spheres[0].end_center = Some(Vector::new(1.5, 0.0, -8.0, 1.0));
+        scene.shutter = (0.0, 1.0);
+        scene.samples = 4;
+        scene.seed = 42;
+        let raytracer = Raytracer::new(scene.clone());
+        let render = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            pool.install(|| raytracer.trace_region(Region::full((32, 24))).1)
+        };
+        let single = render(1);
+        assert!(single == render(4));
+
+        scene.seed = 43;
+        let (_, reseeded) = Raytracer::new(scene).trace_region(Region::full((32, 24)));
+        assert!(single != reseeded);
+    }
+
+    #[test]
+    fn test_trace_region_matches_full_render() {
+        let raytracer = Raytracer::new(test_scene());
+        let (_, full) = raytracer.trace_region(Region::full(raytracer.scene.resolution));
+        let (crop, pixels) = raytracer.trace_region(Region::new(10, 5, 8, 6));
+        assert_eq!(crop, Region::new(10, 5, 8, 6));
+        for y in 0..crop.height {
+            for x in 0..crop.width {
+                let expected = full[((crop.y + y) * 32 + crop.x + x) as usize];
+                assert_eq!(pixels[(y * crop.width + x) as usize], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_auto_exposure_meters_crops_on_the_whole_frame() {
+        let mut scene = test_scene();
+        scene.exposure = Some(Exposure { auto : true, ..Exposure::default() });
+        // wider than the metering pass, which then skips pixels
+        scene.resolution = (160, 120);
+        let full = Region::full(scene.resolution);
+        let crop = Region::new(0, 0, 4, 4);
+        // a crop of the dark background alone, exposed first
+        let raytracer = Raytracer::new(scene.clone());
+        let (_, mut pixels) = raytracer.trace_region(crop);
+        raytracer.expose(&mut pixels);
+        let raytracer = Raytracer::new(scene);
+        let (_, mut image) = raytracer.trace_region(full);
+        raytracer.expose(&mut image);
+        assert_eq!(pixels[5], image[160 + 1]);
+        assert!(image[60 * 160 + 80] != raytracer.trace_pixel(80, 60));
+    }
+
+    #[test]
+    fn test_trace_region_is_clipped_to_image() {
+        let raytracer = Raytracer::new(test_scene());
+        let (crop, pixels) = raytracer.trace_region(Region::new(28, 20, 10, 10));
+        assert_eq!(crop, Region::new(28, 20, 4, 4));
+        assert_eq!(pixels.len(), 16);
+        let (crop, pixels) = raytracer.trace_region(Region::new(40, 40, 10, 10));
+        assert!(crop.is_empty());
+        assert!(pixels.is_empty());
+    }
+
+    #[test]
+    fn test_trace_aovs() {
+        let raytracer = Raytracer::new(test_scene());
+        let (region, aovs) = raytracer.trace_aovs(Region::full(raytracer.scene.resolution), &[Aov::Depth, Aov::Normal, Aov::ObjectIndex]);
+        assert_eq!(aovs.len(), 3);
+        assert_eq!(aovs[0].pixels.len(), region.area());
+        // the sphere fills the middle of the frame, its front is 6 units away
+        let center = (12 * 32 + 16) as usize;
+        assert!((aovs[0].pixels[center].r - 6.0).abs() < 0.05);
+        assert!(aovs[1].pixels[center].b > 0.99);
+        assert_eq!(aovs[2].pixels[center].r, 0.0);
+        // the corner sees the background
+        assert!(aovs[0].pixels[0].r.is_infinite());
+        assert_eq!(aovs[2].pixels[0].r, -1.0);
+    }
+
+    #[test]
+    fn test_passes_add_up_to_region() {
+        let mut scene = test_scene();
+        scene.samples = 4;
+        let raytracer = Raytracer::new(scene);
+        let region = Region::new(4, 4, 10, 8);
+        let mut sum = vec![Color::new(0.0, 0.0, 0.0); region.area()];
+        for pass in 0..4 {
+            let (_, pixels) = raytracer.trace_pass(region, pass);
+            for (total, color) in sum.iter_mut().zip(pixels) {
+                *total = *total + color;
+            }
+        }
+        let average : Vec<Color> = sum.iter().map(|c| *c * 0.25).collect();
+        assert_eq!(average, raytracer.trace_region(region).1);
+    }
+
+    #[test]
+    fn test_tiled_render_matches_rows() {
+        let raytracer = Raytracer::new(test_scene());
+        let region = Region::new(3, 1, 25, 20);
+        let (_, rows) = raytracer.trace_region(region);
+        let (_, tiled) = raytracer.trace_region_tiled(region, 7);
+        assert_eq!(rows, tiled);
+    }
+
+    #[test]
+    fn test_trace_tile_fills_its_buffer() {
+        let raytracer = Raytracer::new(test_scene());
+        let tile = Region::new(4, 2, 5, 3);
+        let mut pixels = vec![Color::new(-1.0, -1.0, -1.0); tile.area()];
+        raytracer.trace_tile(tile, &mut pixels);
+        assert_eq!(pixels[6], raytracer.trace_pixel(5, 3));
+        assert!(pixels.iter().all(|c| c.r >= 0.0));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "buffer doesn't fit tile")]
+    fn test_trace_tile_checks_its_buffer() {
+        let raytracer = Raytracer::new(test_scene());
+        raytracer.trace_tile(Region::new(0, 0, 4, 4), &mut [Color::new(0.0, 0.0, 0.0); 15]);
+    }
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+/// A rectangle of pixels. `(x, y)` is the top left corner, measured from the
+/// top left of the image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x : i32,
+    pub y : i32,
+    pub width : i32,
+    pub height : i32,
+}
+
+impl Region {
+    pub fn new(x : i32, y : i32, width : i32, height : i32) -> Self {
+        Region {
+            x,
+            y,
+            width,
+            height
+        }
+    }
+
+    /// The region covering a whole image of the given `(width, height)`.
+    pub fn full(resolution : (i32, i32)) -> Self {
+        Region::new(0, 0, resolution.0, resolution.1)
+    }
+
+    pub fn area(&self) -> usize {
+        (self.width.max(0) * self.height.max(0)) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    pub fn contains(&self, x : i32, y : i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    /// The overlap of two regions, or `None` if they do not touch.
+    pub fn intersect(&self, other : &Region) -> Option<Region> {
+        let x0 = self.x.max(other.x);
+        let y0 = self.y.max(other.y);
+        let x1 = (self.x + self.width).min(other.x + other.width);
+        let y1 = (self.y + self.height).min(other.y + other.height);
+        let region = Region::new(x0, y0, x1 - x0, y1 - y0);
+        if region.is_empty() {
+            None
+        } else {
+            Some(region)
+        }
+    }
+
+    /// Splits the region into `tile_size` squares in row-major order. Tiles on
+    /// the right and bottom edges are cut short to stay inside the region.
+    pub fn tiles(&self, tile_size : i32) -> Vec<Region> {
+        if tile_size <= 0 {
+            panic!("tile size must be positive");
+        }
+        let mut tiles = Vec::new();
+        let mut y = self.y;
+        while y < self.y + self.height {
+            let mut x = self.x;
+            while x < self.x + self.width {
+                let width = tile_size.min(self.x + self.width - x);
+                let height = tile_size.min(self.y + self.height - y);
+                tiles.push(Region::new(x, y, width, height));
+                x += tile_size;
+            }
+            y += tile_size;
+        }
+        tiles
+    }
+
+    /// Parses `x,y,width,height`.
+    pub fn parse(s : &str) -> Option<Region> {
+        let values = s.split(',')
+            .map(|v| v.trim().parse::<i32>())
+            .collect::<Result<Vec<i32>, _>>()
+            .ok()?;
+        if values.len() != 4 {
+            return None;
+        }
+        Some(Region::new(values[0], values[1], values[2], values[3]))
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{} at ({}, {})", self.width, self.height, self.x, self.y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_region_intersect() {
+        let a = Region::new(0, 0, 10, 10);
+        let b = Region::new(5, 5, 10, 10);
+        assert_eq!(a.intersect(&b), Some(Region::new(5, 5, 5, 5)));
+        assert_eq!(a.intersect(&Region::new(20, 20, 5, 5)), None);
+    }
+
+    #[test]
+    fn test_region_tiles_cover_region() {
+        let region = Region::new(3, 2, 10, 7);
+        let tiles = region.tiles(4);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[0], Region::new(3, 2, 4, 4));
+        assert_eq!(tiles[5], Region::new(11, 6, 2, 3));
+        let area : usize = tiles.iter().map(|t| t.area()).sum();
+        assert_eq!(area, region.area());
+    }
+
+    #[test]
+    fn test_region_parse() {
+        assert_eq!(Region::parse("1, 2,30,40"), Some(Region::new(1, 2, 30, 40)));
+        assert_eq!(Region::parse("1,2,3"), None);
+        assert_eq!(Region::parse("a,b,c,d"), None);
+    }
+}
@@ -0,0 +1,58 @@
+use crate::sampling::{to_unit, SampleIndex, Sampler};
+
+const PRIMES : [u32; 16] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53];
+
+/// The digits of `i` in `base` mirrored around the radix point.
+pub fn radical_inverse(base : u32, mut i : u32) -> f32 {
+    let inv_base = 1.0 / base as f32;
+    let mut factor = inv_base;
+    let mut value = 0.0;
+    while i > 0 {
+        value += (i % base) as f32 * factor;
+        i /= base;
+        factor *= inv_base;
+    }
+    // rounding can land on 1.0 for long expansions
+    value.min(1.0 - f32::EPSILON)
+}
+
+/// The Halton sequence, one prime base per coordinate. Each pixel shifts
+/// its points by a random offset, wrapping around at 1 (a Cranley-Patterson
+/// rotation), so neighbouring pixels don't repeat the same pattern.
+pub struct HaltonSampler {
+    pub seed : u64,
+}
+
+impl Sampler for HaltonSampler {
+    fn get_1d(&self, at : &SampleIndex, coordinate : u32) -> f32 {
+        let point = radical_inverse(PRIMES[coordinate as usize % PRIMES.len()], at.index);
+        let shifted = point + to_unit(at.hash(self.seed, coordinate) as u32);
+        let wrapped = if shifted >= 1.0 { shifted - 1.0 } else { shifted };
+        // the sum can round up to 1.0 before wrapping
+        wrapped.min(1.0 - f32::EPSILON)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_radical_inverse() {
+        assert_eq!(radical_inverse(2, 0), 0.0);
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert_eq!(radical_inverse(3, 1), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn test_pixels_are_rotated_apart() {
+        let sampler = HaltonSampler { seed : 3 };
+        let first = |x, y| sampler.get_2d(&SampleIndex::new(x, y, 0, 16), 0);
+        assert_ne!(first(0, 0), first(1, 0));
+        assert_ne!(first(0, 0), first(0, 1));
+        // a rotation keeps the spacing between points
+        let at = |index| sampler.get_1d(&SampleIndex::new(5, 7, index, 16), 0);
+        assert!(((at(1) - at(0)).rem_euclid(1.0) - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,14 @@
+use crate::sampling::rng::{mix64, Rng};
+use crate::sampling::{SampleIndex, Sampler};
+
+/// Uncorrelated random numbers, hashed from the pixel, the sample and the
+/// coordinate.
+pub struct IndependentSampler {
+    pub seed : u64,
+}
+
+impl Sampler for IndependentSampler {
+    fn get_1d(&self, at : &SampleIndex, coordinate : u32) -> f32 {
+        Rng::for_sample(self.seed ^ mix64(coordinate as u64 + 1), at.x, at.y, at.index).next_f32()
+    }
+}
@@ -0,0 +1,184 @@
+pub mod halton;
+pub mod independent;
+pub mod rng;
+pub mod sobol;
+pub mod stratified;
+
+use core::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use halton::HaltonSampler;
+use independent::IndependentSampler;
+use rng::mix64;
+use sobol::SobolSampler;
+use stratified::StratifiedSampler;
+
+/// What a sample's coordinates are used for. Each takes its own coordinates
+/// so that, say, where a ray crosses the pixel doesn't correlate with the
+/// moment it is traced. Only the pixel position and time are sampled so
+/// far; new dimensions go after `Time`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dimension {
+    // 2D, the position inside the pixel
+    Pixel,
+    // 1D, the moment within the shutter interval
+    Time,
+}
+
+impl Dimension {
+    /// The first coordinate the dimension uses, 2D ones take the next one
+    /// too.
+    pub fn offset(&self) -> u32 {
+        match self {
+            Dimension::Pixel => 0,
+            Dimension::Time => 2,
+        }
+    }
+}
+
+/// Which sample of which pixel is being taken.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SampleIndex {
+    pub x : i32,
+    pub y : i32,
+    pub index : u32,
+    // how many samples the pixel gets, 0 when that isn't known up front as
+    // in progressive rendering
+    pub count : u32,
+}
+
+impl SampleIndex {
+    pub fn new(x : i32, y : i32, index : u32, count : u32) -> Self {
+        SampleIndex { x, y, index, count }
+    }
+
+    // a hash of the pixel and `coordinate`, for decorrelating pixels
+    pub(crate) fn hash(&self, seed : u64, coordinate : u32) -> u64 {
+        let pixel = (self.x as u32 as u64) << 32 | self.y as u32 as u64;
+        mix64(mix64(seed ^ pixel) ^ coordinate as u64)
+    }
+}
+
+/// Produces the numbers in [0, 1) that drive every random choice of a
+/// render. Samplers are stateless, any sample can be asked for in any order
+/// from any thread and always comes out the same.
+pub trait Sampler : Send + Sync {
+    /// Coordinate `coordinate` of sample `at`.
+    fn get_1d(&self, at : &SampleIndex, coordinate : u32) -> f32;
+
+    /// Coordinates `coordinate` and `coordinate + 1`, which are meant to be
+    /// used together.
+    fn get_2d(&self, at : &SampleIndex, coordinate : u32) -> (f32, f32) {
+        (self.get_1d(at, coordinate), self.get_1d(at, coordinate + 1))
+    }
+
+    fn sample_1d(&self, at : &SampleIndex, dimension : Dimension) -> f32 {
+        self.get_1d(at, dimension.offset())
+    }
+
+    fn sample_2d(&self, at : &SampleIndex, dimension : Dimension) -> (f32, f32) {
+        self.get_2d(at, dimension.offset())
+    }
+}
+
+/// The samplers a scene can pick.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    // uncorrelated random numbers
+    Independent,
+    // one jittered sample per stratum, for a known number of samples
+    Stratified,
+    // Halton points, rotated differently in every pixel
+    #[default]
+    Halton,
+    // Owen scrambled Sobol points, decorrelated between pixels
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn build(&self, seed : u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler { seed }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler { seed }),
+            SamplerKind::Halton => Box::new(HaltonSampler { seed }),
+            SamplerKind::Sobol => Box::new(SobolSampler { seed }),
+        }
+    }
+}
+
+impl FromStr for SamplerKind {
+    type Err = String;
+
+    fn from_str(s : &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "independent" | "random" => Ok(SamplerKind::Independent),
+            "stratified" => Ok(SamplerKind::Stratified),
+            "halton" => Ok(SamplerKind::Halton),
+            "sobol" => Ok(SamplerKind::Sobol),
+            _ => Err(format!("unknown sampler: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for SamplerKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// maps a 24 bit or wider integer to [0, 1)
+pub(crate) fn to_unit(bits : u32) -> f32 {
+    (bits >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS : [SamplerKind; 4] = [SamplerKind::Independent, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol];
+
+    #[test]
+    fn test_samples_are_in_range_and_repeatable() {
+        for kind in KINDS {
+            let (a, b) = (kind.build(5), kind.build(5));
+            for index in 0..64 {
+                let at = SampleIndex::new(3, 9, index, 64);
+                let (u, v) = a.sample_2d(&at, Dimension::Pixel);
+                assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v), "{}", kind);
+                assert_eq!((u, v), b.sample_2d(&at, Dimension::Pixel));
+                let t = a.sample_1d(&at, Dimension::Time);
+                assert!((0.0..1.0).contains(&t), "{}", kind);
+                assert_eq!(t, b.sample_1d(&at, Dimension::Time));
+            }
+        }
+    }
+
+    #[test]
+    fn test_low_discrepancy_samplers_fill_every_stratum() {
+        for kind in [SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let sampler = kind.build(11);
+            let mut strata = [0; 16];
+            for index in 0..16 {
+                let t = sampler.get_1d(&SampleIndex::new(1, 2, index, 16), 0);
+                strata[(t * 16.0) as usize] += 1;
+            }
+            assert_eq!(strata, [1; 16], "{}", kind);
+        }
+    }
+
+    #[test]
+    fn test_parse_kind() {
+        assert_eq!("Sobol".parse::<SamplerKind>().unwrap(), SamplerKind::Sobol);
+        assert!("blue_noise".parse::<SamplerKind>().is_err());
+        assert_eq!(serde_json::from_str::<SamplerKind>("\"stratified\"").unwrap(), SamplerKind::Stratified);
+    }
+}
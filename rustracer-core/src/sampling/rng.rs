@@ -0,0 +1,77 @@
+const MULTIPLIER : u64 = 6364136223846793005;
+
+/// The splitmix64 finalizer, which spreads every input bit over the whole
+/// output.
+pub fn mix64(mut x : u64) -> u64 {
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58476d1ce4e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+/// A PCG32 generator. Renders don't share one: each pixel sample builds its
+/// own from the scene seed and where it is, so images come out bit-identical
+/// however rayon splits the work.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state : u64,
+    // odd, picks one of 2^63 streams
+    increment : u64,
+}
+
+impl Rng {
+    pub fn new(seed : u64, stream : u64) -> Self {
+        let mut rng = Rng { state : 0, increment : (stream << 1) | 1 };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(mix64(seed));
+        rng.next_u32();
+        rng
+    }
+
+    /// The generator for sample `sample` of pixel `(x, y)`.
+    pub fn for_sample(seed : u64, x : i32, y : i32, sample : u32) -> Self {
+        let pixel = (x as u32 as u64) << 32 | y as u32 as u64;
+        Rng::new(seed, mix64(pixel ^ mix64(sample as u64)))
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    /// Uniform in [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        // 24 bits is all an f32 mantissa holds
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_streams_are_reproducible() {
+        let mut a = Rng::for_sample(7, 10, 20, 3);
+        let mut b = Rng::for_sample(7, 10, 20, 3);
+        for _ in 0..16 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+        let first = Rng::for_sample(7, 10, 20, 3).next_u32();
+        assert_ne!(first, Rng::for_sample(8, 10, 20, 3).next_u32());
+        assert_ne!(first, Rng::for_sample(7, 20, 10, 3).next_u32());
+        assert_ne!(first, Rng::for_sample(7, 10, 20, 4).next_u32());
+    }
+
+    #[test]
+    fn test_uniform() {
+        let mut rng = Rng::new(1, 2);
+        let values : Vec<f32> = (0..10000).map(|_| rng.next_f32()).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        let mean = values.iter().sum::<f32>() / values.len() as f32;
+        assert!((mean - 0.5).abs() < 0.01);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::sampling::{to_unit, SampleIndex, Sampler};
+
+// the first two dimensions of the Sobol sequence; the second has direction
+// numbers v[k] = v[k - 1] ^ (v[k - 1] >> 1)
+fn sobol(index : u32, dimension : u32) -> u32 {
+    if dimension == 0 {
+        return index.reverse_bits();
+    }
+    let (mut result, mut v, mut i) = (0, 1u32 << 31, index);
+    while i != 0 {
+        if i & 1 != 0 {
+            result ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+    }
+    result
+}
+
+fn laine_karras_permutation(mut x : u32, seed : u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+/// Owen scrambling: flips every digit depending on the digits above it.
+fn nested_uniform_scramble(x : u32, seed : u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+/// Hash based Owen scrambled Sobol points (Burley, "Practical Hash-based
+/// Owen Scrambling"). Every 2D pair uses the first two Sobol dimensions with
+/// its own scramble and sample order, so pairs don't correlate with each
+/// other and the sequence can be extended one sample at a time.
+pub struct SobolSampler {
+    pub seed : u64,
+}
+
+impl SobolSampler {
+    fn seeds(&self, at : &SampleIndex, coordinate : u32) -> (u32, u32) {
+        let hash = at.hash(self.seed, coordinate);
+        (hash as u32, (hash >> 32) as u32)
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn get_1d(&self, at : &SampleIndex, coordinate : u32) -> f32 {
+        let (order, scramble) = self.seeds(at, coordinate);
+        let index = nested_uniform_scramble(at.index, order);
+        to_unit(nested_uniform_scramble(sobol(index, 0), scramble))
+    }
+
+    fn get_2d(&self, at : &SampleIndex, coordinate : u32) -> (f32, f32) {
+        let (order, scramble) = self.seeds(at, coordinate);
+        let index = nested_uniform_scramble(at.index, order);
+        (
+            to_unit(nested_uniform_scramble(sobol(index, 0), scramble)),
+            to_unit(nested_uniform_scramble(sobol(index, 1), scramble ^ 0x9e3779b9)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sobol_points() {
+        // (0, 0), (0.5, 0.5), (0.25, 0.75), (0.75, 0.25)
+        let points : Vec<(u32, u32)> = (0..4).map(|i| (sobol(i, 0) >> 30, sobol(i, 1) >> 30)).collect();
+        assert_eq!(points, vec![(0, 0), (2, 2), (1, 3), (3, 1)]);
+    }
+
+    #[test]
+    fn test_scrambled_quadrants() {
+        // any four consecutive aligned samples cover all quadrants
+        let sampler = SobolSampler { seed : 9 };
+        let mut quadrants = [0; 4];
+        for index in 4..8 {
+            let (u, v) = sampler.get_2d(&SampleIndex::new(2, 7, index, 0), 0);
+            quadrants[(v * 2.0) as usize * 2 + (u * 2.0) as usize] += 1;
+        }
+        assert_eq!(quadrants, [1; 4]);
+    }
+}
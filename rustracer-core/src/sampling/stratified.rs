@@ -0,0 +1,103 @@
+use crate::sampling::independent::IndependentSampler;
+use crate::sampling::{SampleIndex, Sampler};
+
+/// A permutation of 0..n picked by `seed`, found by cycle walking a hash
+/// that is a bijection on the next power of two (Kensler, "Correlated
+/// Multi-Jittered Sampling").
+pub fn permute(mut i : u32, n : u32, seed : u32) -> u32 {
+    let mut w = n - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= seed;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= seed >> 16;
+        i ^= (i & w) >> 4;
+        i ^= seed >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= seed >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | seed >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < n {
+            break;
+        }
+    }
+    (i.wrapping_add(seed)) % n
+}
+
+/// Splits each coordinate into `count` strata, or 2D pairs into a grid, and
+/// puts one jittered sample in each. The strata are visited in a different
+/// order in every pixel and coordinate so they don't line up. Without a
+/// known count it falls back to independent samples.
+pub struct StratifiedSampler {
+    pub seed : u64,
+}
+
+impl StratifiedSampler {
+    fn jitter(&self) -> IndependentSampler {
+        IndependentSampler { seed : self.seed }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn get_1d(&self, at : &SampleIndex, coordinate : u32) -> f32 {
+        let jitter = self.jitter().get_1d(at, coordinate);
+        if at.count == 0 || at.index >= at.count {
+            return jitter;
+        }
+        let stratum = permute(at.index, at.count, at.hash(self.seed, coordinate) as u32);
+        ((stratum as f32 + jitter) / at.count as f32).min(1.0 - f32::EPSILON)
+    }
+
+    fn get_2d(&self, at : &SampleIndex, coordinate : u32) -> (f32, f32) {
+        let (jx, jy) = (self.jitter().get_1d(at, coordinate), self.jitter().get_1d(at, coordinate + 1));
+        if at.count == 0 || at.index >= at.count {
+            return (jx, jy);
+        }
+        // the smallest grid with a cell for every sample
+        let nx = (at.count as f32).sqrt().ceil() as u32;
+        let ny = at.count.div_ceil(nx);
+        let cell = permute(at.index, nx * ny, at.hash(self.seed, coordinate) as u32);
+        (
+            (((cell % nx) as f32 + jx) / nx as f32).min(1.0 - f32::EPSILON),
+            (((cell / nx) as f32 + jy) / ny as f32).min(1.0 - f32::EPSILON),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permute_is_a_permutation() {
+        for n in [1, 5, 16, 100] {
+            let mut seen : Vec<u32> = (0..n).map(|i| permute(i, n, 1234)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..n).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_grid_strata() {
+        let sampler = StratifiedSampler { seed : 3 };
+        let mut cells = [0; 9];
+        for index in 0..9 {
+            let (u, v) = sampler.get_2d(&SampleIndex::new(0, 0, index, 9), 0);
+            cells[(v * 3.0) as usize * 3 + (u * 3.0) as usize] += 1;
+        }
+        assert_eq!(cells, [1; 9]);
+    }
+}
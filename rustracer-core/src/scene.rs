@@ -1,22 +1,46 @@
 use std::{fs::File, io::Read, fmt};
+use std::error::Error;
+use std::path::Path;
 
 //use crate::graphics::texture::Texture;
+use crate::animation::{Animation, IndexedTrack, Track};
+use crate::classic;
+use crate::camera::exposure::Exposure;
+use crate::camera::lens::Lens;
 use crate::graphics::{light::Light, material::Material};
 use crate::math::sphere::Sphere;
-use crate::math::triangle::Triangle;
+use crate::math::triangle::{MeshMotion, Triangle};
 use crate::math::vector::Vector;
+use crate::migration::{self, CURRENT_VERSION};
+use crate::sampling::SamplerKind;
 use crate::graphics::color::Color;
+use crate::graphics::bloom::Bloom;
+use crate::graphics::colorspace::ColorEncoding;
+use crate::graphics::tonemap::DisplayTransform;
 use serde::{Deserialize, Serialize};
-use wavefront::Vertex;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Scene{
+    // the layout the scene was written in, documents are migrated to
+    // `CURRENT_VERSION` before they get here
+    #[serde(default = "current_version")]
+    pub version : u32,
+    #[serde(default)]
     pub materials : Vec<Material>,
+    #[serde(default)]
     pub spheres : Vec<Sphere>,
+    #[serde(default)]
     pub lights : Vec<Light>,
+    // image files that `Material::texture` indexes into, kept so scenes
+    // round trip although nothing samples them yet
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub textures : Vec<String>,
+    #[serde(default)]
     pub obj_file : String,
     #[serde(skip)]
     pub triangles : Vec<Triangle>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mesh_motion : Option<MeshMotion>,
 
     pub eye_pos : Vector,
     pub view_dir : Vector,
@@ -26,62 +50,314 @@ pub struct Scene{
 
     // (width, height)
     pub resolution : (i32, i32),
+    #[serde(default)]
     pub bkg_color : Color,
+    // how material, background and depth cue colours are written
+    #[serde(default, skip_serializing_if = "ColorEncoding::is_working")]
+    pub color_encoding : ColorEncoding,
+    // missed rays get zero coverage instead of `bkg_color`, for images with
+    // an alpha channel
+    #[serde(default)]
+    pub transparent_background : bool,
+    // width of the view for parallel projection
+    #[serde(default)]
     pub frustum_width : f32,
+    #[serde(default)]
     pub parallel : bool,
+    #[serde(default)]
+    pub lens : Lens,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exposure : Option<Exposure>,
+    // how radiance is turned into 8 bit images
+    #[serde(default)]
+    pub display : DisplayTransform,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bloom : Option<Bloom>,
+    // (open, close) times of the shutter, objects move between 0.0 and 1.0
+    #[serde(default)]
+    pub shutter : (f32, f32),
+    #[serde(default = "default_samples")]
+    pub samples : u32,
+    // seeds the random numbers, the same seed gives the same image
+    #[serde(default)]
+    pub seed : u64,
+    // where in the pixel, shutter interval and so on each sample goes
+    #[serde(default)]
+    pub sampler : SamplerKind,
+    // depth cueing blends towards `dc` from alpha.1 at dist.0 to alpha.0 at
+    // dist.1, the defaults turn it off
+    #[serde(default)]
     pub dc: Color,
+    #[serde(default = "default_depth_cue_alpha")]
     pub alpha : (f32, f32),
+    #[serde(default)]
     pub dist : (f32, f32),
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub animation : Option<Animation>,
+}
+
+fn default_samples() -> u32 {
+    1
+}
+
+fn current_version() -> u32 {
+    CURRENT_VERSION
+}
+
+fn default_depth_cue_alpha() -> (f32, f32) {
+    (1.0, 1.0)
 }
 
 impl Scene {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(materials : Vec<Material>, spheres : Vec<Sphere>, lights : Vec<Light>, triangles : Vec<Triangle>, eye_pos : Vector, view_dir : Vector, up_dir : Vector, hfov : f32, resolution : (i32, i32), alpha : (f32, f32), dist : (f32, f32), bkg_color : Color, frustum_width : f32, depth_cue : Color, parallel : bool, obj_file : String) -> Self {
         Scene {
-            materials : materials,
-            spheres : spheres,
-            lights : lights,
-            triangles : triangles,
-            obj_file: obj_file,
-            eye_pos : eye_pos,
-            view_dir : view_dir,
-            up_dir : up_dir,
+            version : CURRENT_VERSION,
+            materials,
+            spheres,
+            lights,
+            textures : Vec::new(),
+            triangles,
+            obj_file,
+            eye_pos,
+            view_dir,
+            up_dir,
             dc: depth_cue,
-            alpha : alpha,
-            dist : dist,
-            hfov : hfov,
-            resolution : resolution,
-            bkg_color : bkg_color,
-            frustum_width : frustum_width,
-            parallel : parallel
-        }
-    }
-
-    pub fn from_file(filename: &str) -> Self {
-        let mut file = match File::open(filename) {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("Error opening file {}: {}", filename, e);
-                std::process::exit(1);
-            }
-        };
-    
+            alpha,
+            dist,
+            hfov,
+            resolution,
+            bkg_color,
+            color_encoding : ColorEncoding::default(),
+            transparent_background : false,
+            frustum_width,
+            parallel,
+            lens : Lens::default(),
+            exposure : None,
+            display : DisplayTransform::default(),
+            bloom : None,
+            shutter : (0.0, 0.0),
+            samples : 1,
+            seed : 0,
+            sampler : SamplerKind::default(),
+            mesh_motion : None,
+            animation : None
+        }
+    }
+
+    /// A copy of the scene with its animation tracks evaluated at `frame`.
+    pub fn at_frame(&self, frame : f32) -> Scene {
+        let mut scene = self.clone();
+        if let Some(animation) = &self.animation {
+            animation.apply(&mut scene, frame);
+        }
+        scene
+    }
+
+    /// Converts the colours written in `color_encoding` to the linear
+    /// working space. Does nothing once they are.
+    pub fn decode_colors(&mut self) {
+        let encoding = self.color_encoding;
+        if encoding.is_working() {
+            return;
+        }
+        for material in &mut self.materials {
+            material.diffuse = encoding.decode(material.diffuse);
+            material.specular = encoding.decode(material.specular);
+        }
+        self.bkg_color = encoding.decode(self.bkg_color);
+        self.dc = encoding.decode(self.dc);
+        self.color_encoding = ColorEncoding::default();
+    }
+
+    /// Reads, parses and validates the scene in `filename`.
+    pub fn load(filename : &str) -> Result<Self, SceneError> {
         let mut contents = String::new();
-        if let Err(e) = file.read_to_string(&mut contents) {
-            eprintln!("Error reading file {}: {}", filename, e);
-            std::process::exit(1);
-        }
-        match serde_json::from_str(&contents) {
-            Ok(scene) => scene,
-            Err(e) => {
-                eprintln!("Error parsing scene file {}: {}", filename, e);
-                std::process::exit(1);
+        File::open(filename)?.read_to_string(&mut contents)?;
+        Self::parse(&contents, SceneFormat::from_filename(filename))
+    }
+
+    /// Parses and validates a JSON scene document.
+    pub fn from_json(json : &str) -> Result<Self, SceneError> {
+        Self::parse(json, SceneFormat::Json)
+    }
+
+    /// Parses and validates a scene document, upgrading it first if it was
+    /// written for an older version.
+    pub fn parse(text : &str, format : SceneFormat) -> Result<Self, SceneError> {
+        let scene = Self::parse_unchecked(text, format)?.0;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// `parse` for a document that has already been read into JSON values.
+    pub fn from_document(document : serde_json::Value) -> Result<Self, SceneError> {
+        let scene = Self::from_document_unchecked(document)?.0;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    // the scene and the version its document was written in
+    fn parse_unchecked(text : &str, format : SceneFormat) -> Result<(Self, u32), SceneError> {
+        if format == SceneFormat::Classic {
+            return Ok((classic::parse(text)?, CURRENT_VERSION));
+        }
+        Self::from_document_unchecked(format.parse(text)?)
+    }
+
+    fn from_document_unchecked(mut document : serde_json::Value) -> Result<(Self, u32), SceneError> {
+        let version = migration::migrate(&mut document)?;
+        let scene = serde_json::from_value(document).map_err(|e| SceneError::Parse(e.to_string()))?;
+        Ok((scene, version))
+    }
+
+    /// Rewrites the scene in `filename` in the current layout, returning the
+    /// version it was in. Files that are already current are left alone.
+    /// The scene isn't validated, upgrading a broken scene keeps it broken.
+    /// TOML and YAML files with comments are refused, since writing the
+    /// scene back out would drop them.
+    pub fn upgrade_file(filename : &str) -> Result<u32, SceneError> {
+        let text = std::fs::read_to_string(filename)?;
+        let format = SceneFormat::from_filename(filename);
+        let (scene, version) = Self::parse_unchecked(&text, format)?;
+        if version == CURRENT_VERSION {
+            return Ok(version);
+        }
+        // a # inside a string is taken for a comment too, which only errs on
+        // the side of leaving the file alone
+        if matches!(format, SceneFormat::Toml | SceneFormat::Yaml) && text.contains('#') {
+            return Err(SceneError::Encoding(format!("{} has comments that rewriting it would drop, upgrade it by hand", filename)));
+        }
+        scene.write_to_file(filename)?;
+        Ok(version)
+    }
+
+    /// Checks everything the renderer would otherwise trip over halfway
+    /// through a render, reporting every problem found with the path of the
+    /// offending value in the scene document.
+    pub fn validate(&self) -> Result<(), SceneError> {
+        let mut v = Validator::default();
+        let (width, height) = self.resolution;
+        // the camera spaces pixels 1 / (n - 1) of the image apart
+        v.check(width >= 2, "resolution[0]", format!("the image must be at least 2 pixels wide, got {}", width));
+        v.check(height >= 2, "resolution[1]", format!("the image must be at least 2 pixels high, got {}", height));
+        if self.parallel {
+            v.positive("frustum_width", self.frustum_width);
+        } else {
+            v.finite("hfov", self.hfov);
+            v.check(self.hfov > 0.0 && self.hfov < 180.0, "hfov", format!("field of view must be between 0 and 180 degrees, got {}", self.hfov));
+        }
+        v.vector("eye_pos", &self.eye_pos);
+        let view_ok = v.direction("view_dir", &self.view_dir);
+        let up_ok = v.direction("up_dir", &self.up_dir);
+        if view_ok && up_ok {
+            let (mut view_dir, mut up_dir) = (self.view_dir, self.up_dir);
+            view_dir.normalize();
+            up_dir.normalize();
+            let cos = view_dir.dot(&up_dir);
+            v.check(cos.abs() <= 0.9, "up_dir", "view direction and up direction are too close to parallel".to_string());
+        }
+        v.color("bkg_color", &self.bkg_color);
+        v.positive("color_encoding.scale", self.color_encoding.scale);
+        v.check(self.samples >= 1, "samples", "must be at least 1, got 0".to_string());
+
+        let distortion = &self.lens.distortion;
+        for (name, value) in [("k1", distortion.k1), ("k2", distortion.k2), ("k3", distortion.k3), ("p1", distortion.p1), ("p2", distortion.p2)] {
+            v.finite(&format!("lens.distortion.{}", name), value);
+        }
+        // red and blue are traced through the film scaled by 1 +- this
+        v.range("lens.chromatic_aberration", self.lens.chromatic_aberration, -1.0, 1.0, false);
+        v.range("lens.vignetting", self.lens.vignetting, 0.0, 1.0, true);
+        if let Some(exposure) = &self.exposure {
+            v.positive("exposure.iso", exposure.iso);
+            v.positive("exposure.shutter_speed", exposure.shutter_speed);
+            v.positive("exposure.f_stop", exposure.f_stop);
+            if let Some(ev) = exposure.ev {
+                v.finite("exposure.ev", ev);
             }
+            v.positive("exposure.key", exposure.key);
+            v.finite("exposure.compensation", exposure.compensation);
         }
+        if let Some(bloom) = &self.bloom {
+            v.non_negative("bloom.threshold", bloom.threshold);
+            v.non_negative("bloom.intensity", bloom.intensity);
+        }
+        v.color("dc", &self.dc);
+        v.pair("alpha", self.alpha);
+        v.pair("dist", self.dist);
+        v.pair("shutter", self.shutter);
+
+        for (i, material) in self.materials.iter().enumerate() {
+            let path = format!("materials[{}]", i);
+            v.color(&format!("{}.diffuse", path), &material.diffuse);
+            v.color(&format!("{}.specular", path), &material.specular);
+            for (name, value) in [("k_a", material.k_a), ("k_d", material.k_d), ("k_s", material.k_s), ("alpha", material.alpha), ("index_of_refraction", material.index_of_refraction)] {
+                v.finite(&format!("{}.{}", path, name), value);
+            }
+            if let Some(texture) = material.texture {
+                v.check(
+                    texture >= 0 && (texture as usize) < self.textures.len(),
+                    &format!("{}.texture", path),
+                    format!("texture {} does not exist, the scene has {} textures", texture, self.textures.len())
+                );
+            }
+        }
+        for (i, sphere) in self.spheres.iter().enumerate() {
+            let path = format!("spheres[{}]", i);
+            v.vector(&format!("{}.center", path), &sphere.center);
+            if let Some(end_center) = &sphere.end_center {
+                v.vector(&format!("{}.end_center", path), end_center);
+            }
+            v.positive(&format!("{}.radius", path), sphere.radius);
+            v.check(
+                sphere.material_index < self.materials.len(),
+                &format!("{}.material_index", path),
+                format!("material {} does not exist, the scene has {} materials", sphere.material_index, self.materials.len())
+            );
+        }
+        for (i, triangle) in self.triangles.iter().enumerate() {
+            v.check(
+                triangle.material_index < self.materials.len(),
+                &format!("triangles[{}].material_index", i),
+                format!("material {} does not exist, the scene has {} materials", triangle.material_index, self.materials.len())
+            );
+        }
+        if let Some(motion) = &self.mesh_motion {
+            v.vector("mesh_motion.start", &motion.start);
+            v.vector("mesh_motion.end", &motion.end);
+            v.vector("mesh_motion.start_rotation", &motion.start_rotation);
+            v.vector("mesh_motion.end_rotation", &motion.end_rotation);
+            v.positive("mesh_motion.start_scale", motion.start_scale);
+            v.positive("mesh_motion.end_scale", motion.end_scale);
+        }
+        for (i, light) in self.lights.iter().enumerate() {
+            let path = format!("lights[{}]", i);
+            v.vector(&format!("{}.v", path), &light.v);
+            let (a, b, c) = light.attenuation;
+            for (j, value) in [a, b, c].into_iter().enumerate() {
+                v.finite(&format!("{}.attenuation[{}]", path, j), value);
+            }
+            v.finite(&format!("{}.i", path), light.i);
+        }
+        if let Some(animation) = &self.animation {
+            if let Some(track) = &animation.eye_pos {
+                v.track("animation.eye_pos", track, Validator::vector);
+            }
+            if let Some(track) = &animation.view_dir {
+                v.track("animation.view_dir", track, Validator::direction);
+            }
+            v.indexed_tracks("animation.sphere_centers", &animation.sphere_centers, self.spheres.len(), "spheres", Validator::vector);
+            v.indexed_tracks("animation.light_positions", &animation.light_positions, self.lights.len(), "lights", Validator::vector);
+            v.indexed_tracks("animation.material_colors", &animation.material_colors, self.materials.len(), "materials", Validator::color);
+        }
+        v.finish()
     }
 
-    pub fn write_to_file(&self, filename : &str) {
-        let serialized: String = serde_json::to_string(&self).unwrap();
-        std::fs::write(filename, serialized).expect("Unable to write file");
+    /// Writes the scene in the format `filename`'s extension asks for, so
+    /// loading and writing converts between them.
+    pub fn write_to_file(&self, filename : &str) -> Result<(), SceneError> {
+        std::fs::write(filename, SceneFormat::from_filename(filename).write(self)?)?;
+        Ok(())
     }
 
 }
@@ -95,3 +371,394 @@ impl fmt::Display for Scene {
         )
     }
 }
+
+/// A value in a scene that can't be rendered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    // where the value sits in the scene document, e.g. spheres[2].radius
+    pub path : String,
+    pub message : String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(String),
+    // the scene can't be represented in the format it's written in
+    Encoding(String),
+    // the scene was written by a newer build
+    UnsupportedVersion(u32),
+    // every problem validation found
+    Invalid(Vec<Problem>),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(e) => write!(f, "scene file error: {}", e),
+            SceneError::Parse(e) => write!(f, "error parsing scene: {}", e),
+            SceneError::Encoding(e) => write!(f, "error writing scene: {}", e),
+            SceneError::UnsupportedVersion(v) => write!(f, "scene version {} is newer than the supported version {}", v, CURRENT_VERSION),
+            SceneError::Invalid(problems) => {
+                write!(f, "invalid scene:")?;
+                for problem in problems {
+                    write!(f, "\n    {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e : std::io::Error) -> Self {
+        SceneError::Io(e)
+    }
+}
+
+/// The file formats scenes can be written in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SceneFormat {
+    Json,
+    // allows comments, like YAML
+    Toml,
+    Yaml,
+    // the text format of the C++ raytracer, which can only be read
+    Classic,
+}
+
+impl SceneFormat {
+    /// The format for `filename`'s extension, JSON for anything else.
+    pub fn from_filename(filename : &str) -> Self {
+        let extension = Path::new(filename).extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("toml") => SceneFormat::Toml,
+            Some("yaml") | Some("yml") => SceneFormat::Yaml,
+            Some("txt") => SceneFormat::Classic,
+            _ => SceneFormat::Json,
+        }
+    }
+
+    // every format is read into a JSON value so migrations only deal with one
+    fn parse(&self, text : &str) -> Result<serde_json::Value, SceneError> {
+        let parsed = match self {
+            SceneFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            SceneFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+            SceneFormat::Yaml => serde_yaml::from_str(text).map_err(|e| e.to_string()),
+            SceneFormat::Classic => Err("classic scenes don't go through a document".to_string()),
+        };
+        parsed.map_err(SceneError::Parse)
+    }
+
+    fn write(&self, scene : &Scene) -> Result<String, SceneError> {
+        // triangles are skipped when serializing, only classic scenes list them
+        if !scene.triangles.is_empty() {
+            return Err(SceneError::Encoding(format!("only classic scenes hold triangles, writing would drop all {} of them", scene.triangles.len())));
+        }
+        let written = match self {
+            SceneFormat::Json => serde_json::to_string_pretty(scene).map(|s| s + "\n").map_err(|e| e.to_string()),
+            // through JSON text, which has f32s in their shortest form, as
+            // toml would write 0.2 widened to 0.20000000298023224
+            SceneFormat::Toml => serde_json::to_string(scene)
+                .and_then(|json| serde_json::from_str(&json))
+                .map_err(|e| e.to_string())
+                .and_then(|value| toml::to_string_pretty(&without_nulls(value)).map_err(|e| e.to_string())),
+            SceneFormat::Yaml => serde_yaml::to_string(scene).map_err(|e| e.to_string()),
+            SceneFormat::Classic => Err("scenes can't be written in the classic text format".to_string()),
+        };
+        written.map_err(SceneError::Encoding)
+    }
+}
+
+// TOML has no null, an absent key reads back as None the same way
+fn without_nulls(value : serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::Object(fields) => fields.into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, without_nulls(value)))
+            .collect(),
+        serde_json::Value::Array(values) => values.into_iter().map(without_nulls).collect(),
+        value => value,
+    }
+}
+
+// collects the problems `Scene::validate` finds
+#[derive(Default)]
+struct Validator {
+    problems : Vec<Problem>,
+}
+
+impl Validator {
+    fn check(&mut self, ok : bool, path : &str, message : String) {
+        if !ok {
+            self.problems.push(Problem { path : path.to_string(), message });
+        }
+    }
+
+    fn finite(&mut self, path : &str, value : f32) -> bool {
+        self.check(value.is_finite(), path, format!("expected a finite number, got {}", value));
+        value.is_finite()
+    }
+
+    fn positive(&mut self, path : &str, value : f32) {
+        if self.finite(path, value) {
+            self.check(value > 0.0, path, format!("must be greater than 0, got {}", value));
+        }
+    }
+
+    fn non_negative(&mut self, path : &str, value : f32) {
+        if self.finite(path, value) {
+            self.check(value >= 0.0, path, format!("must not be negative, got {}", value));
+        }
+    }
+
+    // between `min` and `max`, which are allowed if `inclusive`
+    fn range(&mut self, path : &str, value : f32, min : f32, max : f32, inclusive : bool) {
+        if self.finite(path, value) {
+            let ok = if inclusive { value >= min && value <= max } else { value > min && value < max };
+            let (open, close) = if inclusive { ('[', ']') } else { ('(', ')') };
+            self.check(ok, path, format!("must be in {}{}, {}{}, got {}", open, min, max, close, value));
+        }
+    }
+
+    fn pair(&mut self, path : &str, (a, b) : (f32, f32)) {
+        self.finite(&format!("{}[0]", path), a);
+        self.finite(&format!("{}[1]", path), b);
+    }
+
+    fn vector(&mut self, path : &str, v : &Vector) -> bool {
+        [("x", v.x), ("y", v.y), ("z", v.z), ("w", v.w)].into_iter()
+            .fold(true, |ok, (name, value)| self.finite(&format!("{}.{}", path, name), value) && ok)
+    }
+
+    fn direction(&mut self, path : &str, v : &Vector) -> bool {
+        if !self.vector(path, v) {
+            return false;
+        }
+        self.check(v.dot(v) > 0.0, path, "direction has zero length".to_string());
+        v.dot(v) > 0.0
+    }
+
+    fn color(&mut self, path : &str, c : &Color) {
+        for (name, value) in [("r", c.r), ("g", c.g), ("b", c.b)] {
+            self.finite(&format!("{}.{}", path, name), value);
+        }
+    }
+
+    fn track<T, R>(&mut self, path : &str, track : &Track<T>, value : fn(&mut Self, &str, &T) -> R) {
+        let mut previous = None;
+        for (i, key) in track.keyframes.iter().enumerate() {
+            let path = format!("{}.keyframes[{}]", path, i);
+            if self.finite(&format!("{}.frame", path), key.frame) {
+                if let Some(previous) = previous {
+                    self.check(key.frame > previous, &format!("{}.frame", path), format!("keyframes must be in increasing frame order, got {} after {}", key.frame, previous));
+                }
+                previous = Some(key.frame);
+            }
+            value(self, &format!("{}.value", path), &key.value);
+        }
+    }
+
+    fn indexed_tracks<T, R>(&mut self, path : &str, tracks : &[IndexedTrack<T>], len : usize, list : &str, value : fn(&mut Self, &str, &T) -> R) {
+        for (i, track) in tracks.iter().enumerate() {
+            let path = format!("{}[{}]", path, i);
+            self.check(track.index < len, &format!("{}.index", path), format!("index {} is out of range, the scene has {} {}", track.index, len, list));
+            self.track(&path, &track.track, value);
+        }
+    }
+
+    fn finish(self) -> Result<(), SceneError> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(SceneError::Invalid(self.problems))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Keyframe;
+    use crate::raytracer::Raytracer;
+
+    fn golden_scene() -> Scene {
+        Scene::from_json(include_str!("../tests/golden/spheres.json")).unwrap()
+    }
+
+    #[test]
+    fn test_animation_problems_are_reported() {
+        let mut scene = golden_scene();
+        let mut animation : Animation = serde_json::from_str(r#"{
+            "sphere_centers": [{
+                "index": 7,
+                "keyframes": [{"frame": 0, "value": {"x": 0, "y": 0, "z": -8, "w": 1}}]
+            }],
+            "material_colors": [{
+                "index": 0,
+                "keyframes": [
+                    {"frame": 0, "value": {"r": 1, "g": 0, "b": 0}},
+                    {"frame": 10, "value": {"r": 0, "g": 0, "b": 1}}
+                ]
+            }]
+        }"#).unwrap();
+        animation.material_colors[0].track.keyframes[0].frame = f32::NAN;
+        animation.material_colors[0].track.keyframes.push(Keyframe { frame : 5.0, value : Color::new(0.0, 1.0, 0.0) });
+        scene.animation = Some(animation);
+        let problems = match scene.validate() {
+            Err(SceneError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid scene, got {:?}", other),
+        };
+        let paths : Vec<&str> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec![
+            "animation.sphere_centers[0].index",
+            "animation.material_colors[0].keyframes[0].frame",
+            "animation.material_colors[0].keyframes[2].frame",
+        ]);
+    }
+
+    #[test]
+    fn test_problems_are_reported_by_path() {
+        let mut scene = golden_scene();
+        scene.resolution = (0, 100);
+        scene.hfov = 190.0;
+        scene.view_dir = Vector::new(0.0, 0.0, 0.0, 0.0);
+        scene.eye_pos.y = f32::NAN;
+        scene.spheres[1].material_index = scene.materials.len();
+        scene.textures = vec!["earth.ppm".to_string()];
+        scene.materials[0].texture = Some(0);
+        scene.materials[1].texture = Some(1);
+        let problems = match scene.validate() {
+            Err(SceneError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid scene, got {:?}", other),
+        };
+        let paths : Vec<&str> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec!["resolution[0]", "hfov", "eye_pos.y", "view_dir", "materials[1].texture", "spheres[1].material_index"]);
+    }
+
+    #[test]
+    fn test_camera_settings_are_checked() {
+        let mut scene = golden_scene();
+        // long but not parallel
+        scene.view_dir = Vector::new(0.0, 0.0, -10.0, 0.0);
+        scene.up_dir = Vector::new(0.0, 5.0, -5.0, 0.0);
+        assert!(scene.validate().is_ok());
+        Raytracer::new(scene.clone());
+
+        scene.color_encoding.scale = 0.0;
+        scene.samples = 0;
+        scene.lens.chromatic_aberration = 1.0;
+        scene.lens.vignetting = f32::NAN;
+        scene.exposure = Some(Exposure { f_stop : -2.0, ..Exposure::default() });
+        scene.bloom = Some(Bloom { intensity : -1.0, ..Bloom::default() });
+        let problems = match scene.validate() {
+            Err(SceneError::Invalid(problems)) => problems,
+            other => panic!("expected an invalid scene, got {:?}", other),
+        };
+        let paths : Vec<&str> = problems.iter().map(|p| p.path.as_str()).collect();
+        assert_eq!(paths, vec!["color_encoding.scale", "samples", "lens.chromatic_aberration", "lens.vignetting", "exposure.f_stop", "bloom.intensity"]);
+    }
+
+    #[test]
+    fn test_optional_fields_default() {
+        let scene = Scene::from_json(r#"{
+            "eye_pos": {"x": 0, "y": 0, "z": 0, "w": 1},
+            "view_dir": {"x": 0, "y": 0, "z": -1, "w": 0},
+            "up_dir": {"x": 0, "y": 1, "z": 0, "w": 0},
+            "hfov": 60,
+            "resolution": [4, 3]
+        }"#).unwrap();
+        assert_eq!(scene.version, CURRENT_VERSION);
+        assert!(scene.spheres.is_empty() && !scene.parallel);
+        assert_eq!(scene.alpha, (1.0, 1.0));
+    }
+
+    #[test]
+    fn test_upgrade_file() {
+        let filename = std::env::temp_dir().join(format!("rustracer_upgrade_{}.json", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        std::fs::write(filename, include_str!("../tests/golden/spheres.json")).unwrap();
+        assert_eq!(Scene::upgrade_file(filename).unwrap(), 0);
+        assert_eq!(Scene::upgrade_file(filename).unwrap(), CURRENT_VERSION);
+        let upgraded = Scene::load(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert_eq!(upgraded.spheres.len(), golden_scene().spheres.len());
+    }
+
+    #[test]
+    fn test_toml_and_yaml_round_trip() {
+        let mut scene = golden_scene();
+        scene.animation = Some(Animation::default());
+        scene.spheres[0].end_center = Some(Vector::new(1.0, 2.0, 3.0, 1.0));
+        let json = serde_json::to_value(&scene).unwrap();
+        let dir = std::env::temp_dir();
+        for extension in ["toml", "yaml", "json"] {
+            let filename = dir.join(format!("rustracer_round_trip_{}.{}", std::process::id(), extension));
+            let filename = filename.to_str().unwrap();
+            scene.write_to_file(filename).unwrap();
+            let loaded = Scene::load(filename);
+            std::fs::remove_file(filename).unwrap();
+            assert_eq!(serde_json::to_value(loaded.unwrap()).unwrap(), json, "{}", extension);
+        }
+    }
+
+    #[test]
+    fn test_toml_floats_are_written_short() {
+        let mut scene = golden_scene();
+        scene.materials[0].k_d = 0.2;
+        let toml = SceneFormat::Toml.write(&scene).unwrap();
+        assert!(toml.contains("k_d = 0.2\n"), "{}", toml);
+        assert!(!toml.contains("0.20000000298023224"));
+    }
+
+    #[test]
+    fn test_commented_files_are_not_upgraded() {
+        let filename = std::env::temp_dir().join(format!("rustracer_upgrade_{}.toml", std::process::id()));
+        let filename = filename.to_str().unwrap();
+        let text = format!("# the golden spheres\n{}", SceneFormat::Toml.write(&golden_scene()).unwrap().replace("version = 1", "version = 0"));
+        std::fs::write(filename, &text).unwrap();
+        let result = Scene::upgrade_file(filename);
+        let after = std::fs::read_to_string(filename).unwrap();
+        std::fs::remove_file(filename).unwrap();
+        assert!(matches!(result, Err(SceneError::Encoding(_))));
+        assert_eq!(after, text);
+    }
+
+    #[test]
+    fn test_toml_scene_with_comments() {
+        let scene = Scene::parse(r#"
+            hfov = 60
+            resolution = [4, 3]
+            # looking down -z
+            eye_pos = { x = 0, y = 0, z = 0, w = 1 }
+            view_dir = { x = 0, y = 0, z = -1, w = 0 }
+            up_dir = { x = 0, y = 1, z = 0, w = 0 }
+
+            [[lights]]
+            v = { x = 0, y = 5, z = 0, w = 1 }
+            attenuation = [1, 0, 0]
+            i = 1.0
+        "#, SceneFormat::Toml).unwrap();
+        assert_eq!(scene.lights.len(), 1);
+        assert_eq!(SceneFormat::from_filename("a/b.YML"), SceneFormat::Yaml);
+        assert_eq!(SceneFormat::from_filename("scene"), SceneFormat::Json);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        assert!(matches!(Scene::load("does/not/exist.json"), Err(SceneError::Io(_))));
+        assert!(matches!(Scene::from_json("{\"materials\": []"), Err(SceneError::Parse(_))));
+        let mut scene = golden_scene();
+        scene.up_dir = scene.view_dir * 2.0;
+        let e = scene.validate().unwrap_err();
+        assert_eq!(e.to_string(), "invalid scene:\n    up_dir: view direction and up direction are too close to parallel");
+    }
+}
@@ -0,0 +1,194 @@
+use core::fmt;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::{Serialize, Serializer};
+
+thread_local! {
+    // what this thread has traced so far, added to a `Counters` by `record`
+    static THREAD_COUNTS : Cell<RayStats> = const { Cell::new(RayStats { primary_rays : 0, shadow_rays : 0, intersection_tests : 0 }) };
+}
+
+fn count(f : impl FnOnce(&mut RayStats)) {
+    THREAD_COUNTS.with(|counts| {
+        let mut stats = counts.get();
+        f(&mut stats);
+        counts.set(stats);
+    });
+}
+
+pub fn count_primary_rays(n : u64) {
+    count(|stats| stats.primary_rays += n);
+}
+
+pub fn count_shadow_rays(n : u64) {
+    count(|stats| stats.shadow_rays += n);
+}
+
+pub fn count_intersection_tests(n : u64) {
+    count(|stats| stats.intersection_tests += n);
+}
+
+/// Intersection tests run on the calling thread so far. The difference
+/// around tracing a pixel is what that pixel cost.
+pub fn thread_intersection_tests() -> u64 {
+    THREAD_COUNTS.with(|counts| counts.get().intersection_tests)
+}
+
+/// Ray and intersection counters, shared by all the render threads. Rays are
+/// counted on the thread that traces them and only added here by `record`,
+/// so the threads don't fight over the atomics on every ray.
+#[derive(Debug, Default)]
+pub struct Counters {
+    primary_rays : AtomicU64,
+    shadow_rays : AtomicU64,
+    intersection_tests : AtomicU64,
+}
+
+impl Counters {
+    /// Runs `f` and adds the rays it traced on the calling thread. Calls
+    /// don't nest, an inner `record` would be counted twice.
+    pub fn record<T>(&self, f : impl FnOnce() -> T) -> T {
+        let before = THREAD_COUNTS.with(Cell::get);
+        let result = f();
+        let after = THREAD_COUNTS.with(Cell::get);
+        self.primary_rays.fetch_add(after.primary_rays - before.primary_rays, Ordering::Relaxed);
+        self.shadow_rays.fetch_add(after.shadow_rays - before.shadow_rays, Ordering::Relaxed);
+        self.intersection_tests.fetch_add(after.intersection_tests - before.intersection_tests, Ordering::Relaxed);
+        result
+    }
+
+    pub fn snapshot(&self) -> RayStats {
+        RayStats {
+            primary_rays : self.primary_rays.load(Ordering::Relaxed),
+            shadow_rays : self.shadow_rays.load(Ordering::Relaxed),
+            intersection_tests : self.intersection_tests.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn reset(&self) {
+        for counter in [&self.primary_rays, &self.shadow_rays, &self.intersection_tests] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// The counters at one point in time. There are no secondary ray or BVH
+/// node counts: the shader traces no reflection or refraction rays, and
+/// every ray is tested against every object without an acceleration
+/// structure, so both would always be 0.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RayStats {
+    pub primary_rays : u64,
+    pub shadow_rays : u64,
+    pub intersection_tests : u64,
+}
+
+impl RayStats {
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.shadow_rays
+    }
+}
+
+fn as_seconds<S : Serializer>(duration : &Duration, serializer : S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Wall clock time spent in each phase of a render, in seconds when
+/// serialized.
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timings {
+    #[serde(serialize_with = "as_seconds")]
+    pub scene_load : Duration,
+    // building the Raytracer's camera and sampler; there is no acceleration
+    // structure to build yet
+    #[serde(serialize_with = "as_seconds")]
+    pub setup : Duration,
+    #[serde(serialize_with = "as_seconds")]
+    pub tracing : Duration,
+    // exposure, denoising and bloom
+    #[serde(serialize_with = "as_seconds")]
+    pub post_process : Duration,
+    #[serde(serialize_with = "as_seconds")]
+    pub encoding : Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.scene_load + self.setup + self.tracing + self.post_process + self.encoding
+    }
+
+    /// The phases as (name, duration) pairs, in the order they run.
+    pub fn phases(&self) -> [(&'static str, Duration); 5] {
+        [
+            ("scene_load", self.scene_load),
+            ("setup", self.setup),
+            ("tracing", self.tracing),
+            ("post_process", self.post_process),
+            ("encoding", self.encoding),
+        ]
+    }
+}
+
+/// Runs `f`, adding the time it took to `phase`.
+pub fn timed<T>(phase : &mut Duration, f : impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    *phase += start.elapsed();
+    result
+}
+
+#[derive(Serialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderStats {
+    pub rays : RayStats,
+    pub timings : Timings,
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rays = &self.rays;
+        writeln!(f, "rays: {} primary, {} shadow", rays.primary_rays, rays.shadow_rays)?;
+        writeln!(f, "intersection tests: {}", rays.intersection_tests)?;
+        for (name, duration) in self.timings.phases() {
+            writeln!(f, "{:<14}{:>9.3}s", name, duration.as_secs_f64())?;
+        }
+        let seconds = self.timings.tracing.as_secs_f64();
+        if seconds > 0.0 {
+            writeln!(f, "{:.2} Mrays/s while tracing", rays.total_rays() as f64 / seconds / 1e6)?;
+        }
+        write!(f, "{:<14}{:>9.3}s", "total", self.timings.total().as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_counters() {
+        let counters = Counters::default();
+        // counted before recording starts, left out
+        count_primary_rays(7);
+        counters.record(|| {
+            count_primary_rays(3);
+            count_shadow_rays(2);
+            count_intersection_tests(10);
+        });
+        let stats = counters.snapshot();
+        assert_eq!((stats.primary_rays, stats.shadow_rays, stats.intersection_tests), (3, 2, 10));
+        assert_eq!(stats.total_rays(), 5);
+        counters.reset();
+        assert_eq!(counters.snapshot(), RayStats::default());
+    }
+
+    #[test]
+    fn test_timings_serialize_as_seconds() {
+        let mut timings = Timings::default();
+        timed(&mut timings.tracing, || ());
+        timings.encoding = Duration::from_millis(250);
+        let json = serde_json::to_value(timings).unwrap();
+        assert_eq!(json["encoding"], 0.25);
+        assert!(timings.total() >= timings.encoding);
+    }
+}
@@ -0,0 +1,102 @@
+// Renders every scene in tests/golden and compares it with the PNG of the same
+// name next to it. Run with RUSTRACER_UPDATE_GOLDEN=1 to (re)write the
+// reference images after an intended change in shading. On failure the render
+// and an amplified difference image are left in the target directory.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustracer_core::compare::{diff_image, psnr, ssim};
+use rustracer_core::raytracer::Raytracer;
+use rustracer_core::scene::Scene;
+
+const MIN_PSNR : f64 = 40.0;
+const MIN_SSIM : f64 = 0.99;
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn render(scene_file : &Path) -> (u32, u32, Vec<u8>) {
+    let scene = Scene::load(scene_file.to_str().unwrap()).unwrap();
+    let raytracer = Arc::new(Raytracer::new(scene));
+    let (width, height) = (raytracer.scene.resolution.0 as u32, raytracer.scene.resolution.1 as u32);
+    let pixels = Arc::clone(&raytracer).trace_rays();
+    (width, height, raytracer.scene.display.to_rgb8(&pixels))
+}
+
+// the bytes are already display encoded, so this bypasses output::encode
+fn write_png(path : &Path, width : u32, height : u32, rgb : &[u8]) {
+    let file = fs::File::create(path).unwrap();
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    encoder.write_header().unwrap().write_image_data(rgb).unwrap();
+}
+
+fn read_png(path : &Path) -> Option<(u32, u32, Vec<u8>)> {
+    let decoder = png::Decoder::new(fs::File::open(path).ok()?);
+    let mut reader = decoder.read_info().ok()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).ok()?;
+    if info.color_type != png::ColorType::Rgb || info.bit_depth != png::BitDepth::Eight {
+        return None;
+    }
+    data.truncate(info.buffer_size());
+    Some((info.width, info.height, data))
+}
+
+#[test]
+fn golden_images() {
+    let update = std::env::var_os("RUSTRACER_UPDATE_GOLDEN").is_some();
+    let out_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let mut scenes : Vec<PathBuf> = fs::read_dir(golden_dir()).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "json" || e == "txt"))
+        .collect();
+    scenes.sort();
+    assert!(!scenes.is_empty(), "no golden scenes found");
+
+    let mut failures = Vec::new();
+    for scene_file in &scenes {
+        let name = scene_file.file_stem().unwrap().to_string_lossy().to_string();
+        let reference_file = scene_file.with_extension("png");
+        let (width, height, actual) = render(scene_file);
+
+        if update {
+            write_png(&reference_file, width, height, &actual);
+            println!("{}: reference written", name);
+            continue;
+        }
+        let (ref_width, ref_height, expected) = match read_png(&reference_file) {
+            Some(reference) => reference,
+            None => {
+                failures.push(format!("{}: no readable 8 bit RGB reference at {}", name, reference_file.display()));
+                continue;
+            }
+        };
+        if (ref_width, ref_height) != (width, height) {
+            failures.push(format!("{}: rendered {}x{} but the reference is {}x{}", name, width, height, ref_width, ref_height));
+            continue;
+        }
+
+        let psnr = psnr(&actual, &expected);
+        let ssim = ssim(&actual, &expected, width as usize, height as usize);
+        println!("{}: PSNR {:.2} dB, SSIM {:.5}", name, psnr, ssim);
+        if psnr < MIN_PSNR || ssim < MIN_SSIM {
+            let actual_file = out_dir.join(format!("{}.png", name));
+            let diff_file = out_dir.join(format!("{}_diff.png", name));
+            write_png(&actual_file, width, height, &actual);
+            write_png(&diff_file, width, height, &diff_image(&actual, &expected));
+            failures.push(format!(
+                "{}: PSNR {:.2} dB (min {}), SSIM {:.5} (min {}); render at {}, difference at {}",
+                name, psnr, MIN_PSNR, ssim, MIN_SSIM, actual_file.display(), diff_file.display()
+            ));
+        }
+    }
+    assert!(failures.is_empty(), "golden images differ:\n{}", failures.join("\n"));
+}
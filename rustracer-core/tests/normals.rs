@@ -0,0 +1,78 @@
+// Triangle hits are shaded with their face normal, or the vertex normals
+// interpolated at the hit, turned to face the incoming ray.
+
+use rustracer_core::graphics::color::Color;
+use rustracer_core::graphics::light::Light;
+use rustracer_core::graphics::material::Material;
+use rustracer_core::math::ray::Ray;
+use rustracer_core::math::triangle::Triangle;
+use rustracer_core::math::vector::Vector;
+use rustracer_core::raytracer::Raytracer;
+use rustracer_core::scene::Scene;
+
+fn point(x : f32, y : f32, z : f32) -> Vector {
+    Vector::new(x, y, z, 1.0)
+}
+
+fn direction(x : f32, y : f32, z : f32) -> Vector {
+    Vector::new(x, y, z, 0.0)
+}
+
+fn triangle(p : (Vector, Vector, Vector), normals : Option<(Vector, Vector, Vector)>) -> Triangle {
+    let n = direction(0.0, 0.0, 1.0);
+    let mut triangle = Triangle::new(p.0, p.1, p.2, n, n, n, [0.0; 3], [0.0; 3], [0.0; 3]);
+    triangle.normals = normals;
+    triangle
+}
+
+// a white, purely diffuse triangle lit head on by a directional light
+fn raytracer(triangle : Triangle) -> Raytracer {
+    let material = Material::new(Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0), 0.2, 0.6, 0.0, 1.0, 0.0, 1, None);
+    Raytracer::new(Scene::new(
+        vec![material],
+        Vec::new(),
+        vec![Light::new(direction(0.0, 0.0, -1.0), (1.0, 0.0, 0.0), 1.0)],
+        vec![triangle],
+        point(0.0, 0.0, 0.0),
+        direction(0.0, 0.0, -1.0),
+        direction(0.0, 1.0, 0.0),
+        45.0,
+        (8, 8),
+        (1.0, 1.0),
+        (0.0, 0.0),
+        Color::new(0.0, 0.0, 0.0),
+        2.0,
+        Color::new(0.0, 0.0, 0.0),
+        false,
+        String::new()
+    ))
+}
+
+fn shade(triangle : Triangle) -> Color {
+    raytracer(triangle).trace(Ray::new(point(0.0, 0.0, 0.0), direction(0.0, 0.0, -1.0)))
+}
+
+#[test]
+fn test_face_normal_faces_the_ray() {
+    let (a, b, c) = (point(-1.0, -1.0, -5.0), point(1.0, -1.0, -5.0), point(0.0, 1.0, -5.0));
+    let n = triangle((a, b, c), None).normal_at([1.0 / 3.0; 3]);
+    assert!(n.x.abs() < 1e-6 && n.y.abs() < 1e-6 && (n.z - 1.0).abs() < 1e-6);
+    // ambient plus full diffuse, whichever way the triangle is wound
+    for winding in [(a, b, c), (a, c, b)] {
+        let color = shade(triangle(winding, None));
+        assert!((color.r - 0.8).abs() < 1e-5, "{:?}", color);
+    }
+}
+
+#[test]
+fn test_vertex_normals_are_interpolated() {
+    let p = (point(-1.0, -1.0, -5.0), point(1.0, -1.0, -5.0), point(0.0, 1.0, -5.0));
+    let normals = (direction(0.0, 0.0, 1.0), direction(1.0, 0.0, 0.0), direction(0.0, 0.0, 1.0));
+    let n = triangle(p, Some(normals)).normal_at([0.5, 0.5, 0.0]);
+    let expected = 0.5f32.sqrt();
+    assert!((n.x - expected).abs() < 1e-6 && (n.z - expected).abs() < 1e-6);
+
+    // tilted normals catch less of the light than the flat face
+    let color = shade(triangle(p, Some(normals)));
+    assert!(color.r > 0.2 && color.r < 0.8 - 1e-3, "{:?}", color);
+}
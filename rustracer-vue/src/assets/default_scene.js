@@ -15,11 +15,13 @@ export let scene = {
     "hfov":60.0,
     "resolution":[1000,800],
     "bkg_color":{
-        "r":0.2,"g":0.2,"b":0.2
+        "r":51,"g":51,"b":51
     },
+    // colours come straight from the colour pickers
+    "color_encoding": {"space": "srgb", "scale": 255},
     "frustum_width":2.0,
     "parallel":false,
-    "dc": {"r":0.2,"g":0.2,"b":0.2},
+    "dc": {"r":51,"g":51,"b":51},
     "alpha": [0.0, 1.0],
     "dist": [1, 30]
 };